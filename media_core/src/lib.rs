@@ -1,6 +1,7 @@
 // New lib.rs content - simple re-export
 pub mod rtsp;
 pub mod process;
+pub mod metrics;
 
 // Re-export everything from rtsp for backward compatibility
 pub use rtsp::*;
@@ -0,0 +1,238 @@
+//! Prometheus metrics
+//!
+//! Renders the status of the capture cameras and the statistics of `process`
+//! runs in the Prometheus text exposition format, and serves them on
+//! `/metrics` with [`MetricsServer`].
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use tiny_http::{Header, Response};
+
+use crate::process::ProcessingStats;
+use crate::rtsp::http_server::HttpServer;
+use crate::rtsp::manager::CameraStatus;
+use crate::rtsp::reconnect::ConnectionState;
+use crate::rtsp::shutdown::ShutdownSignal;
+
+/// Content type of the text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MetricsConfig {
+    /// Address the `/metrics` endpoint listens on
+    #[serde(default = "default_bind")]
+    pub bind: String,
+}
+
+fn default_bind() -> String {
+    "127.0.0.1:9464".to_string()
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            bind: default_bind(),
+        }
+    }
+}
+
+/// Writes metric families in the text exposition format
+#[derive(Debug, Default)]
+pub struct MetricsWriter {
+    out: String,
+}
+
+impl MetricsWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a metric family; its samples must follow before the next family
+    pub fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+                .collect();
+            let _ = write!(self.out, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.out, " {}", value);
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Metrics of every recording camera
+pub fn render_capture_metrics(statuses: &[CameraStatus]) -> String {
+    let mut writer = MetricsWriter::new();
+
+    // Families as (name, kind, help, value); `None` leaves the camera out
+    type Metric = (&'static str, &'static str, &'static str, fn(&CameraStatus) -> Option<f64>);
+    let metrics: [Metric; 8] = [
+        (
+            "media_core_camera_running",
+            "gauge",
+            "Whether the capture thread of the camera is alive",
+            |status| Some(status.running as u8 as f64),
+        ),
+        (
+            "media_core_camera_connected",
+            "gauge",
+            "Whether the camera has been recording stably",
+            |status| Some((status.state == ConnectionState::Connected) as u8 as f64),
+        ),
+        (
+            "media_core_camera_restarts_total",
+            "counter",
+            "Restarts of the recorder (e.g. FFmpeg) after a failure",
+            |status| Some(status.restarts as f64),
+        ),
+        (
+            "media_core_camera_consecutive_failures",
+            "gauge",
+            "Failed attempts since the camera was last connected",
+            |status| Some(status.consecutive_failures as f64),
+        ),
+        (
+            "media_core_camera_segments_written_total",
+            "counter",
+            "Finished segments",
+            |status| (!status.segments_untracked).then_some(status.segments_closed as f64),
+        ),
+        (
+            "media_core_camera_bytes_written_total",
+            "counter",
+            "Size of the finished segments in bytes",
+            |status| (!status.segments_untracked).then_some(status.bytes_written as f64),
+        ),
+        (
+            "media_core_camera_seconds_since_last_segment",
+            "gauge",
+            "Seconds since the last segment was finished",
+            |status| status.last_segment_at.map(|at| at.elapsed().as_secs_f64()),
+        ),
+        (
+            "media_core_camera_fps",
+            "gauge",
+            "Frames per second written, measured over the last few seconds",
+            |status| status.fps,
+        ),
+    ];
+
+    for (name, kind, help, value) in metrics {
+        writer.family(name, kind, help);
+        for status in statuses {
+            if let Some(value) = value(status) {
+                writer.sample(name, &[("camera", &status.camera_id)], value);
+            }
+        }
+    }
+    writer.finish()
+}
+
+/// `ProcessingStats` of every directory of a `process` run
+pub fn render_processing_metrics(directories: &BTreeMap<String, ProcessingStats>) -> String {
+    let mut writer = MetricsWriter::new();
+
+    type Metric = (&'static str, &'static str, &'static str, fn(&ProcessingStats) -> f64);
+    let metrics: [Metric; 5] = [
+        (
+            "media_core_process_files_processed",
+            "gauge",
+            "Input videos processed",
+            |stats| stats.files_processed as f64,
+        ),
+        (
+            "media_core_process_files_failed",
+            "gauge",
+            "Input videos that failed to process",
+            |stats| stats.files_failed as f64,
+        ),
+        (
+            "media_core_process_bytes_processed",
+            "gauge",
+            "Size of the processed input videos in bytes",
+            |stats| stats.total_size_processed as f64,
+        ),
+        (
+            "media_core_process_errors",
+            "gauge",
+            "Errors reported while processing",
+            |stats| stats.errors.len() as f64,
+        ),
+        (
+            "media_core_process_duration_seconds",
+            "gauge",
+            "Processing time, still growing while the directory is in progress",
+            |stats| {
+                if stats.processing_time.is_zero() {
+                    stats.start_time.elapsed().as_secs_f64()
+                } else {
+                    stats.processing_time.as_secs_f64()
+                }
+            },
+        ),
+    ];
+
+    for (name, kind, help, value) in metrics {
+        writer.family(name, kind, help);
+        for (directory, stats) in directories {
+            writer.sample(name, &[("directory", directory)], value(stats));
+        }
+    }
+    writer.finish()
+}
+
+/// Running `/metrics` endpoint
+pub struct MetricsServer {
+    server: HttpServer,
+}
+
+impl MetricsServer {
+    /// Listen on `bind` and answer `GET /metrics` with the output of `render`
+    /// until `shutdown` is triggered or `stop` is called
+    pub fn start<F>(bind: &str, render: F, shutdown: ShutdownSignal) -> io::Result<Self>
+    where
+        F: Fn() -> String + Send + 'static,
+    {
+        let server = HttpServer::start(bind, "Metrics endpoint", shutdown, move |request| {
+            let path = request.url().split('?').next().unwrap_or_default();
+            let response = if path == "/metrics" {
+                Response::from_string(render())
+                    .with_header(Header::from_bytes("Content-Type", CONTENT_TYPE).unwrap())
+            } else {
+                Response::from_string("Not found").with_status_code(404)
+            };
+            if let Err(e) = request.respond(response) {
+                eprintln!("Metrics endpoint failed to respond: {}", e);
+            }
+        })?;
+        Ok(Self { server })
+    }
+
+    /// Address the server is listening on (resolves port 0)
+    pub fn addr(&self) -> &str {
+        self.server.addr()
+    }
+
+    pub fn stop(&mut self) {
+        self.server.stop();
+    }
+}
@@ -21,7 +21,7 @@ pub use types::{
     ImageFormat, DocumentFormat, get_default_supported_formats
 };
 pub use config::{ProcessConfig, ProcessingOptions, VideoExtractionConfig};
-pub use stats::ProcessingStats;
+pub use stats::{DirectoryStats, ProcessingStats};
 pub use processor::Processor;
 pub use video::VideoProcessor;
 pub use factories::{
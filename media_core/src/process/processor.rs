@@ -6,13 +6,14 @@ use std::time::Duration;
 
 use crate::process::types::{ProcessError, ProcessingMode, FileFormat, VideoFormat, AudioFormat, ImageFormat, DocumentFormat};
 use crate::process::config::{ProcessConfig, ProcessingOptions};
-use crate::process::stats::ProcessingStats;
+use crate::process::stats::{DirectoryStats, ProcessingStats};
 use crate::process::video::VideoProcessor;
 
 /// Main processor struct for handling process operations
 pub struct Processor {
     config: ProcessConfig,
     stats: ProcessingStats,
+    directory_stats: DirectoryStats,
 }
 
 impl Processor {
@@ -35,6 +36,7 @@ impl Processor {
         Ok(Self { 
             config,
             stats: ProcessingStats::new(),
+            directory_stats: DirectoryStats::default(),
         })
     }
 
@@ -460,6 +462,11 @@ impl Processor {
         &self.stats
     }
 
+    /// Get the per-directory statistics of video extraction, updated while it runs
+    pub fn directory_stats(&self) -> DirectoryStats {
+        DirectoryStats::clone(&self.directory_stats)
+    }
+
     /// Get supported file formats
     pub fn get_supported_formats(&self) -> &Vec<FileFormat> {
         &self.config.supported_formats
@@ -472,6 +479,6 @@ impl Processor {
 
     /// Run video extraction processing (matching extraction/processing.rs::run)
     pub fn run_video_extraction(&mut self, config_path: &str) -> Result<(), ProcessError> {
-        VideoProcessor::run_video_extraction(config_path, &mut self.stats)
+        VideoProcessor::run_video_extraction(config_path, &mut self.stats, &self.directory_stats)
     }
 } 
\ No newline at end of file
@@ -1,5 +1,11 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+/// Statistics per input directory, keyed by the configured path.
+/// Shared so they can be read (e.g. for metrics) while a run is in progress.
+pub type DirectoryStats = Arc<Mutex<BTreeMap<String, ProcessingStats>>>;
+
 /// Processing statistics and metrics
 #[derive(Debug, Clone)]
 pub struct ProcessingStats {
@@ -19,7 +19,7 @@ use rayon::prelude::*;
 
 use crate::process::types::ProcessError;
 use crate::process::config::VideoExtractionConfig;
-use crate::process::stats::ProcessingStats;
+use crate::process::stats::{DirectoryStats, ProcessingStats};
 
 /// Video processing functionality
 pub struct VideoProcessor;
@@ -29,6 +29,7 @@ impl VideoProcessor {
     pub fn run_video_extraction(
         config_path: &str,
         stats: &mut ProcessingStats,
+        directory_stats: &DirectoryStats,
     ) -> Result<(), ProcessError> {
         let start_time = Instant::now();
 
@@ -40,6 +41,7 @@ impl VideoProcessor {
             .map_err(|e| ProcessError::ConfigurationError(format!("Error parsing config.json at '{}': {}", e.path(), e)))?;
 
         let config = Arc::new(video_config);
+        directory_stats.lock().unwrap().clear();
         let temp_dirs_created = Arc::new(Mutex::new(Vec::<PathBuf>::new()));
 
         let mut video_files_by_dir: HashMap<String, Vec<PathBuf>> = HashMap::new();
@@ -78,11 +80,12 @@ impl VideoProcessor {
             "sequential" => {
                 println!("Running in sequential mode.");
                 for (dir_path, video_list) in video_files_by_dir {
-                    if let Err(e) = Self::process_video_directory(
+                    if let Err(e) = Self::process_tracked_directory(
                         dir_path.clone(),
                         video_list,
                         Arc::clone(&config),
                         Arc::clone(&temp_dirs_created),
+                        directory_stats,
                     ) {
                         eprintln!("Error processing directory {}: {}", dir_path, e);
                         stats.add_failed_file(format!("Directory {}: {}", dir_path, e));
@@ -100,11 +103,12 @@ impl VideoProcessor {
                 video_files_by_dir
                     .into_par_iter()
                     .for_each(|(dir_path, video_list)| {
-                        if let Err(e) = Self::process_video_directory(
+                        if let Err(e) = Self::process_tracked_directory(
                             dir_path.clone(),
                             video_list,
                             Arc::clone(&config),
                             Arc::clone(&temp_dirs_created),
+                            directory_stats,
                         ) {
                             eprintln!("Error processing directory in parallel {}: {}", dir_path, e);
                         }
@@ -134,6 +138,49 @@ impl VideoProcessor {
         Ok(())
     }
 
+    /// Process a video directory and record its statistics in `directory_stats`
+    fn process_tracked_directory(
+        input_dir_path: String,
+        video_list: Vec<PathBuf>,
+        config: Arc<VideoExtractionConfig>,
+        temp_dirs_created: Arc<Mutex<Vec<PathBuf>>>,
+        directory_stats: &DirectoryStats,
+    ) -> Result<(), ProcessError> {
+        directory_stats
+            .lock()
+            .unwrap()
+            .insert(input_dir_path.clone(), ProcessingStats::new());
+        let video_count = video_list.len() as u64;
+        let video_bytes: u64 = video_list
+            .iter()
+            .filter_map(|path| fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+
+        let result = Self::process_video_directory(
+            input_dir_path.clone(),
+            video_list,
+            config,
+            temp_dirs_created,
+        );
+
+        if let Some(dir_stats) = directory_stats.lock().unwrap().get_mut(&input_dir_path) {
+            match &result {
+                Ok(_) => {
+                    dir_stats.files_processed += video_count;
+                    dir_stats.total_size_processed += video_bytes;
+                }
+                Err(e) => {
+                    // The directory is written to one output video, so all of its inputs failed
+                    dir_stats.files_failed += video_count;
+                    dir_stats.errors.push(format!("Directory {}: {}", input_dir_path, e));
+                }
+            }
+            dir_stats.finalize();
+        }
+        result
+    }
+
     /// Process video directory (matching extraction/processing.rs::process_directory)
     fn process_video_directory(
         input_dir_path: String,
@@ -0,0 +1,213 @@
+//! Recorder backends
+//!
+//! An [`RTSPCapture`] owns a camera's settings, its reconnect policy and the
+//! post-processing of finished segments. Pulling the stream and writing the
+//! segments is left to a [`RecorderBackend`]: the FFmpeg backend stream-copies
+//! with an FFmpeg process, the OpenCV backend decodes and re-encodes frames.
+//! Other backends, such as the [`FakeBackend`] used in tests, plug in the
+//! same way through `RTSPCapture::backend`.
+
+use chrono::Local;
+use opencv::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::rtsp::error::CaptureError;
+use crate::rtsp::segments::ClosedSegment;
+use crate::rtsp::RTSPCapture;
+
+/// Built-in backends selectable per camera
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// Stream copy through an FFmpeg process
+    Ffmpeg,
+    /// Decode and re-encode frames through OpenCV (custom fps, motion, preview)
+    Opencv,
+}
+
+/// Result of polling a running backend
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackendHealth {
+    /// Still recording
+    Running,
+    /// The stream failed or stalled; the capture reconnects according to its policy
+    Failed(CaptureError),
+    /// The backend ended the recording itself (e.g. ESC in the preview window)
+    Finished,
+}
+
+/// What a backend knows about its recording
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BackendStats {
+    /// Segment currently being written, if known
+    pub current_segment: Option<PathBuf>,
+    pub segments_closed: u64,
+    /// Finished segments are not reported (e.g. a rolling HLS window), so
+    /// `segments_closed` says nothing about the recording
+    pub segments_untracked: bool,
+    /// Frames written; only known to backends that decode the stream
+    pub frames_written: u64,
+    pub last_frame_at: Option<Instant>,
+}
+
+/// A way of recording a camera.
+///
+/// The capture calls `start`, then `poll` in a loop until it reports a failure
+/// or shutdown is requested, then `stop`. After a failure `stop` is called
+/// before the next `start`. Backends report every segment they finish through
+/// `RTSPCapture::on_segment_closed`.
+pub trait RecorderBackend: Send {
+    /// Short name for logs
+    fn name(&self) -> &str;
+
+    /// Connect to the camera and start recording; an error counts as a failed attempt
+    fn start(&mut self, capture: &mut RTSPCapture) -> Result<()>;
+
+    /// Do the next piece of work and report whether recording is healthy.
+    /// Implementations pace themselves (read one frame, or sleep while a process runs).
+    fn poll(&mut self, capture: &mut RTSPCapture) -> Result<BackendHealth>;
+
+    /// Close the current segment and continue in a new one
+    fn rotate(&mut self, capture: &mut RTSPCapture) -> Result<()>;
+
+    /// Finalize the open segment and disconnect
+    fn stop(&mut self, capture: &mut RTSPCapture) -> Result<()>;
+
+    fn stats(&self) -> BackendStats;
+}
+
+/// Scripted backend for tests.
+///
+/// Each poll returns the next entry of `script`; once the script is used up it
+/// returns `Finished`, or keeps `Running` until shutdown with `keep_running`.
+/// With `stall_after` the stream stalls after that many frames per connection
+/// and fails once the capture's `stall_timeout` passes, like the OpenCV backend.
+/// Every successful start creates an empty segment file that is reported as
+/// closed on rotate and stop.
+#[derive(Debug, Default)]
+pub struct FakeBackend {
+    /// Number of calls to `start` that fail before one succeeds
+    pub failing_starts: u32,
+    pub script: VecDeque<BackendHealth>,
+    pub keep_running: bool,
+    /// Frames delivered per connection before the stream stalls
+    pub stall_after: Option<u64>,
+    /// Time `stop` takes, like finalizing a segment
+    pub stop_delay: Duration,
+    pub starts: u32,
+    pub stops: u32,
+    stats: BackendStats,
+    sequence: u32,
+    /// Frames delivered since the last start
+    connection_frames: u64,
+    /// Last start or frame, whichever came later
+    last_activity: Option<Instant>,
+}
+
+impl FakeBackend {
+    pub fn new(script: Vec<BackendHealth>) -> Self {
+        Self {
+            script: script.into(),
+            ..Self::default()
+        }
+    }
+
+    fn open_segment(&mut self, capture: &mut RTSPCapture) -> Result<()> {
+        let camera_dir = capture.camera_dir();
+        let path = camera_dir.join(format!(
+            "segment_{}_{:04}.mp4",
+            Local::now().format("%Y%m%d_%H%M%S"),
+            self.sequence
+        ));
+        self.sequence += 1;
+        fs::create_dir_all(&camera_dir)
+            .and_then(|_| fs::write(&path, b""))
+            .map_err(|e| {
+                opencv::Error::new(
+                    opencv::core::StsError,
+                    &format!("Failed to create fake segment: {}", e),
+                )
+            })?;
+        capture.on_segment_started(&path);
+        self.stats.current_segment = Some(path);
+        Ok(())
+    }
+
+    fn close_segment(&mut self, capture: &mut RTSPCapture) {
+        if let Some(path) = self.stats.current_segment.take() {
+            self.stats.segments_closed += 1;
+            capture.on_segment_closed(ClosedSegment::from_path(path));
+        }
+    }
+}
+
+impl RecorderBackend for FakeBackend {
+    fn name(&self) -> &str {
+        "fake"
+    }
+
+    fn start(&mut self, capture: &mut RTSPCapture) -> Result<()> {
+        self.starts += 1;
+        if self.failing_starts > 0 {
+            self.failing_starts -= 1;
+            return Err(opencv::Error::new(
+                opencv::core::StsError,
+                "scripted start failure",
+            ));
+        }
+        self.connection_frames = 0;
+        self.last_activity = Some(Instant::now());
+        self.open_segment(capture)
+    }
+
+    fn poll(&mut self, capture: &mut RTSPCapture) -> Result<BackendHealth> {
+        if self.stall_after.is_some_and(|frames| self.connection_frames >= frames) {
+            let stalled_for = self.last_activity.map_or(Duration::ZERO, |at| at.elapsed());
+            if stalled_for >= capture.stall_timeout {
+                return Ok(BackendHealth::Failed(CaptureError::Timeout(format!(
+                    "no frame for {:.1}s",
+                    stalled_for.as_secs_f64()
+                ))));
+            }
+            capture.shutdown.sleep(Duration::from_millis(10));
+            return Ok(BackendHealth::Running);
+        }
+
+        let health = match self.script.pop_front() {
+            Some(health) => health,
+            None if self.keep_running => {
+                capture.shutdown.sleep(Duration::from_millis(10));
+                BackendHealth::Running
+            }
+            None => BackendHealth::Finished,
+        };
+        if health == BackendHealth::Running {
+            self.connection_frames += 1;
+            self.stats.frames_written += 1;
+            self.stats.last_frame_at = Some(Instant::now());
+            self.last_activity = self.stats.last_frame_at;
+        }
+        Ok(health)
+    }
+
+    fn rotate(&mut self, capture: &mut RTSPCapture) -> Result<()> {
+        self.close_segment(capture);
+        self.open_segment(capture)
+    }
+
+    fn stop(&mut self, capture: &mut RTSPCapture) -> Result<()> {
+        self.stops += 1;
+        thread::sleep(self.stop_delay);
+        self.close_segment(capture);
+        Ok(())
+    }
+
+    fn stats(&self) -> BackendStats {
+        self.stats.clone()
+    }
+}
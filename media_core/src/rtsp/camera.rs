@@ -0,0 +1,193 @@
+//! Per-camera capture configuration
+//!
+//! Each camera has a stable id which names its output directory, so the URL
+//! (IP address, stream path) can change without losing recording history.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::rtsp::backend::BackendKind;
+use crate::rtsp::credentials::{redact_url, CredentialsConfig};
+use crate::rtsp::motion::MotionConfig;
+use crate::rtsp::overlay::OverlayConfig;
+use crate::rtsp::protocol::InputProtocol;
+use crate::rtsp::reconnect::ReconnectPolicy;
+use crate::rtsp::snapshot::SnapshotConfig;
+use crate::rtsp::AudioCodec;
+
+/// RTSP lower transport used to pull the stream, honored by both backends
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RtspTransport {
+    /// RTP interleaved in the RTSP connection
+    #[default]
+    Tcp,
+    /// RTP over unicast UDP; falls back to TCP when no packets arrive
+    Udp,
+    /// RTP over multicast UDP, for encoders that only multicast
+    UdpMulticast,
+    /// RTSP tunnelled through HTTP
+    Http,
+}
+
+impl RtspTransport {
+    /// Value for FFmpeg's `-rtsp_transport` option
+    pub fn as_ffmpeg_str(&self) -> &'static str {
+        match self {
+            RtspTransport::Tcp => "tcp",
+            RtspTransport::Udp => "udp",
+            RtspTransport::UdpMulticast => "udp_multicast",
+            RtspTransport::Http => "http",
+        }
+    }
+}
+
+/// Configuration of a single camera
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CameraConfig {
+    /// Stable identifier, used for the output directory name
+    pub id: String,
+    /// Human readable name used in logs
+    #[serde(default)]
+    pub name: Option<String>,
+    pub url: String,
+    /// Input protocol, detected from the URL when not set
+    #[serde(default)]
+    pub protocol: Option<InputProtocol>,
+    /// Credentials injected into the input URL, kept out of logs and paths
+    #[serde(default)]
+    pub credentials: Option<CredentialsConfig>,
+    #[serde(default)]
+    pub transport: RtspTransport,
+    /// Segment duration in seconds, overrides `saved_time_duration`
+    #[serde(default)]
+    pub segment_duration: Option<u64>,
+    /// Record through OpenCV at this fps, overrides `use_fps`/`fps`
+    #[serde(default)]
+    pub fps: Option<f64>,
+    /// Output subdirectory below `output_directory`, defaults to `camera_<id>`.
+    /// Must be a single path component, see [`CameraConfig::validate`]
+    #[serde(default)]
+    pub output_subdir: Option<String>,
+    /// Reconnection policy, overrides the global `reconnect`
+    #[serde(default)]
+    pub reconnect: Option<ReconnectPolicy>,
+    /// Motion-triggered recording, overrides the global `motion`
+    #[serde(default)]
+    pub motion: Option<MotionConfig>,
+    /// Periodic snapshots, overrides the global `snapshots`
+    #[serde(default)]
+    pub snapshots: Option<SnapshotConfig>,
+    /// Text overlay, overrides the global `overlay`
+    #[serde(default)]
+    pub overlay: Option<OverlayConfig>,
+    /// Recorder backend, overrides the global `backend`
+    #[serde(default)]
+    pub backend: Option<BackendKind>,
+    /// Record audio, overrides the global `audio`
+    #[serde(default)]
+    pub audio: Option<bool>,
+    /// Audio handling, overrides the global `audio_codec`
+    #[serde(default)]
+    pub audio_codec: Option<AudioCodec>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// Cameras a background service (retention, upload) works on. Asked for on
+/// every pass, so cameras added by a configuration reload are included.
+pub type CameraSource = Box<dyn Fn() -> Vec<CameraConfig> + Send>;
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl CameraConfig {
+    /// Camera built from a bare URL of the legacy `rtsp_url`/`rtsp_url_list` fields.
+    ///
+    /// The id is the mangled URL without its credentials and the directory is
+    /// `camera_<id>`. For URLs without credentials this is the name written by
+    /// earlier versions, so existing recordings stay in place; URLs with inline
+    /// credentials record into a new directory.
+    pub fn from_legacy_url(url: &str) -> Self {
+        let id = legacy_camera_id(url);
+        Self {
+            output_subdir: Some(format!("camera_{}", id)),
+            id,
+            name: None,
+            url: url.to_string(),
+            protocol: None,
+            credentials: None,
+            transport: RtspTransport::default(),
+            segment_duration: None,
+            fps: None,
+            reconnect: None,
+            motion: None,
+            snapshots: None,
+            overlay: None,
+            backend: None,
+            audio: None,
+            audio_codec: None,
+            enabled: true,
+        }
+    }
+
+    /// Name used in logs
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.id)
+    }
+
+    /// Check the settings that cannot be caught by deserialization
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(subdir) = &self.output_subdir {
+            if !is_single_path_component(subdir) {
+                return Err(format!(
+                    "Camera {}: output_subdir {:?} must be a single directory name",
+                    self.id, subdir
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Directory name below the output directory
+    pub fn directory_name(&self) -> String {
+        match &self.output_subdir {
+            Some(subdir) => subdir.clone(),
+            None => format!("camera_{}", sanitize_path_component(&self.id)),
+        }
+    }
+}
+
+/// Camera id derived from a URL the way directory names were built before camera ids existed.
+///
+/// Credentials are stripped first so they never end up in a directory name.
+pub fn legacy_camera_id(url: &str) -> String {
+    redact_url(url)
+        .replace("://", "_")
+        .replace('/', "_")
+        .replace(':', "_")
+}
+
+/// Whether `value` names a directory directly below another one
+fn is_single_path_component(value: &str) -> bool {
+    !value.is_empty()
+        && value != "."
+        && value != ".."
+        && !value.contains(['/', '\\', '\0'])
+        && !Path::new(value).has_root()
+}
+
+/// Replace characters that are unsafe in a single path component
+pub fn sanitize_path_component(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,365 @@
+//! Recording catalog
+//!
+//! Every camera directory holds a `catalog.jsonl` with one JSON entry per
+//! finished segment. Entries are appended as segments close, removed when
+//! retention, upload or quarantine take a segment away, and the whole file
+//! can be rebuilt by scanning the segments on disk.
+//!
+//! Writers take an exclusive lock on `catalog.jsonl.lock` next to the
+//! catalog, so the recorder and a `catalog rebuild` run in another process
+//! never lose each other's changes. Rewrites go through a temporary file and
+//! a rename, so readers always see a complete catalog.
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+use crate::rtsp::retention::is_segment_file;
+
+/// Name of the catalog file inside a camera directory
+pub const CATALOG_FILE_NAME: &str = "catalog.jsonl";
+
+/// Lock file guarding changes to the catalog across threads and processes
+pub const CATALOG_LOCK_NAME: &str = "catalog.jsonl.lock";
+
+/// One finished segment
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CatalogEntry {
+    pub camera_id: String,
+    pub path: String,
+    /// RFC 3339 wall-clock start time
+    pub start_time: String,
+    /// RFC 3339 wall-clock end time
+    pub end_time: String,
+    pub duration_secs: f64,
+    pub size_bytes: u64,
+    pub codec: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// `sha256:<hex digest>` of the file contents
+    pub checksum: String,
+}
+
+/// Stream properties read from a segment with ffprobe
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SegmentProbe {
+    pub codec: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub duration_secs: Option<f64>,
+}
+
+/// Catalog of a single camera directory
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    path: PathBuf,
+}
+
+impl Catalog {
+    pub fn for_camera_dir(camera_dir: &Path) -> Self {
+        Self {
+            path: camera_dir.join(CATALOG_FILE_NAME),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Exclusive lock on the catalog, released when the returned file is dropped
+    fn lock(&self) -> io::Result<File> {
+        let camera_dir = self.path.parent().unwrap_or(Path::new("."));
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(camera_dir.join(CATALOG_LOCK_NAME))?;
+        file.lock()?;
+        Ok(file)
+    }
+
+    /// Append one entry as a single JSON line
+    pub fn append(&self, entry: &CatalogEntry) -> io::Result<()> {
+        let line = serde_json::to_string(entry)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let _lock = self.lock()?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)?;
+        file.flush()
+    }
+
+    /// Load all entries, skipping lines that cannot be parsed (e.g. torn by a crash)
+    pub fn load(&self) -> io::Result<Vec<CatalogEntry>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => eprintln!(
+                    "Skipping malformed catalog line in {}: {}",
+                    self.path.display(),
+                    e
+                ),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Paths already present in the catalog
+    pub fn recorded_paths(&self) -> io::Result<HashSet<PathBuf>> {
+        Ok(self
+            .load()?
+            .into_iter()
+            .map(|entry| PathBuf::from(entry.path))
+            .collect())
+    }
+
+    /// Remove the entries of deleted or moved segments, matched by file name.
+    ///
+    /// Returns the number of entries removed.
+    pub fn remove(&self, segments: &[PathBuf]) -> io::Result<usize> {
+        let names: HashSet<&OsStr> = segments.iter().filter_map(|path| path.file_name()).collect();
+        self.retain(|entry| {
+            Path::new(&entry.path)
+                .file_name()
+                .is_none_or(|name| !names.contains(name))
+        })
+    }
+
+    /// Update the size and checksum of a segment's entry after its file was
+    /// replaced (e.g. by a repair). Returns whether the segment had an entry.
+    pub fn refresh(&self, segment: &Path) -> io::Result<bool> {
+        let Some(name) = segment.file_name() else {
+            return Ok(false);
+        };
+        if !self.path.exists() {
+            return Ok(false);
+        }
+        // Hash before locking, like a rebuild
+        let size_bytes = fs::metadata(segment)?.len();
+        let checksum = format!("sha256:{}", sha256_file(segment)?);
+
+        let _lock = self.lock()?;
+        let mut entries = self.load()?;
+        let mut found = false;
+        for entry in entries
+            .iter_mut()
+            .filter(|entry| Path::new(&entry.path).file_name() == Some(name))
+        {
+            entry.size_bytes = size_bytes;
+            entry.checksum = checksum.clone();
+            found = true;
+        }
+        if found {
+            self.write_entries(&entries)?;
+        }
+        Ok(found)
+    }
+
+    /// Remove the entries whose segment no longer exists in the camera directory
+    pub fn prune_missing(&self) -> io::Result<usize> {
+        let camera_dir = self.path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        self.retain(|entry| {
+            Path::new(&entry.path)
+                .file_name()
+                .is_some_and(|name| camera_dir.join(name).exists())
+        })
+    }
+
+    /// Rewrite the catalog with the entries `keep` accepts
+    fn retain<F>(&self, keep: F) -> io::Result<usize>
+    where
+        F: Fn(&CatalogEntry) -> bool,
+    {
+        if !self.path.exists() {
+            return Ok(0);
+        }
+        let _lock = self.lock()?;
+        let entries = self.load()?;
+        let kept: Vec<&CatalogEntry> = entries.iter().filter(|entry| keep(entry)).collect();
+        let removed = entries.len() - kept.len();
+        if removed > 0 {
+            self.write_entries(kept)?;
+        }
+        Ok(removed)
+    }
+
+    /// Replace the catalog atomically; the caller holds the lock
+    fn write_entries<'a>(&self, entries: impl IntoIterator<Item = &'a CatalogEntry>) -> io::Result<()> {
+        let tmp_path = self.path.with_extension("jsonl.tmp");
+        let mut file = File::create(&tmp_path)?;
+        for entry in entries {
+            let line = serde_json::to_string(entry)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            writeln!(file, "{}", line)?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)
+    }
+
+    /// Rebuild the catalog from the segments in its camera directory.
+    ///
+    /// Returns the number of entries written.
+    pub fn rebuild(&self, camera_id: &str) -> io::Result<usize> {
+        let camera_dir = self.path.parent().unwrap_or(Path::new("."));
+        let mut segments: Vec<PathBuf> = fs::read_dir(camera_dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.is_file()
+                    && path
+                        .file_name()
+                        .is_some_and(|name| is_segment_file(&name.to_string_lossy()))
+            })
+            .collect();
+        segments.sort();
+
+        // Segments are described before locking, since hashing and probing take
+        // a while. Entries the recorder appends meanwhile are kept.
+        let mut entries = Vec::new();
+        for segment in &segments {
+            match describe_segment(camera_id, segment, None, None) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => eprintln!("Skipping {} in catalog rebuild: {}", segment.display(), e),
+            }
+        }
+
+        let _lock = self.lock()?;
+        let described: HashSet<PathBuf> = entries
+            .iter()
+            .filter_map(|entry| Path::new(&entry.path).file_name())
+            .map(PathBuf::from)
+            .collect();
+        for entry in self.load()? {
+            let appended = Path::new(&entry.path).file_name().is_some_and(|name| {
+                !described.contains(Path::new(name)) && camera_dir.join(name).exists()
+            });
+            if appended {
+                entries.push(entry);
+            }
+        }
+        let written = entries.len();
+        self.write_entries(&entries)?;
+        Ok(written)
+    }
+}
+
+/// Build the catalog entry of a finished segment.
+///
+/// Start and end times default to the timestamp in the file name and the
+/// probed duration when the recorder did not track them.
+pub fn describe_segment(
+    camera_id: &str,
+    path: &Path,
+    start: Option<DateTime<Local>>,
+    end: Option<DateTime<Local>>,
+) -> io::Result<CatalogEntry> {
+    let metadata = fs::metadata(path)?;
+    let probe = probe_segment(path).unwrap_or_default();
+
+    let start = start
+        .or_else(|| start_time_from_file_name(path))
+        .unwrap_or_else(|| {
+            let modified = metadata.modified().unwrap_or(SystemTime::now());
+            let duration = Duration::from_secs_f64(probe.duration_secs.unwrap_or(0.0).max(0.0));
+            DateTime::<Local>::from(modified - duration)
+        });
+    let end = end.unwrap_or_else(|| {
+        let duration = probe.duration_secs.unwrap_or(0.0).max(0.0);
+        start + chrono::Duration::milliseconds((duration * 1000.0) as i64)
+    });
+    let duration_secs = probe
+        .duration_secs
+        .unwrap_or_else(|| (end - start).num_milliseconds() as f64 / 1000.0);
+
+    Ok(CatalogEntry {
+        camera_id: camera_id.to_string(),
+        path: path.to_string_lossy().to_string(),
+        start_time: start.to_rfc3339(),
+        end_time: end.to_rfc3339(),
+        duration_secs,
+        size_bytes: metadata.len(),
+        codec: probe.codec,
+        width: probe.width,
+        height: probe.height,
+        checksum: format!("sha256:{}", sha256_file(path)?),
+    })
+}
+
+/// Parse the start time from `segment_%Y%m%d_%H%M%S.mp4` or `hls_%Y%m%d_%H%M%S.ts`
+pub fn start_time_from_file_name(path: &Path) -> Option<DateTime<Local>> {
+    let stem = path.file_stem()?.to_str()?;
+    let timestamp = stem
+        .strip_prefix("segment_")
+        .or_else(|| stem.strip_prefix("hls_"))?;
+    let naive = NaiveDateTime::parse_from_str(timestamp, "%Y%m%d_%H%M%S").ok()?;
+    Local.from_local_datetime(&naive).earliest()
+}
+
+/// Read codec, resolution and duration of the first video stream with ffprobe
+pub fn probe_segment(path: &Path) -> Option<SegmentProbe> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=codec_name,width,height:format=duration",
+            "-of",
+            "json",
+        ])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+    let stream = &json["streams"][0];
+    Some(SegmentProbe {
+        codec: stream["codec_name"].as_str().map(str::to_string),
+        width: stream["width"].as_u64().map(|w| w as u32),
+        height: stream["height"].as_u64().map(|h| h as u32),
+        duration_secs: json["format"]["duration"]
+            .as_str()
+            .and_then(|d| d.parse().ok()),
+    })
+}
+
+/// Hex SHA-256 digest of a file
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
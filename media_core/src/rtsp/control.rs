@@ -0,0 +1,251 @@
+//! Local HTTP control and status API
+//!
+//! An optional server next to the capture threads that reports the state of
+//! every camera and starts, stops or restarts single cameras:
+//!
+//! - `GET /cameras` and `GET /cameras/<id>`: status as JSON
+//! - `POST /cameras/<id>/start`, `/stop`, `/restart`; a stop that has to wait
+//!   for the camera to finalize its segment finishes in the background and is
+//!   answered with `202`
+//! - `GET /cameras/<id>/snapshot`: the camera's latest snapshot JPEG
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::SystemTime;
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::rtsp::http_server::HttpServer;
+use crate::rtsp::manager::{CameraStatus, RecorderManager, StoppingCamera};
+use crate::rtsp::shutdown::ShutdownSignal;
+use crate::rtsp::snapshot::{is_snapshot_file, LATEST_SNAPSHOT_NAME};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ControlConfig {
+    /// Address the server listens on; keep it on localhost unless the network is trusted
+    #[serde(default = "default_bind")]
+    pub bind: String,
+}
+
+fn default_bind() -> String {
+    "127.0.0.1:8090".to_string()
+}
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        Self {
+            bind: default_bind(),
+        }
+    }
+}
+
+/// Camera status as reported by the API
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StatusResponse {
+    pub camera_id: String,
+    pub name: String,
+    pub state: String,
+    pub running: bool,
+    pub backend: String,
+    pub current_segment: Option<PathBuf>,
+    pub segments_closed: u64,
+    /// RFC 3339 time of the last decoded frame, for backends that decode
+    pub last_frame_time: Option<String>,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    /// Whether the camera is finalizing its segment after a stop
+    pub stopping: bool,
+}
+
+impl From<&CameraStatus> for StatusResponse {
+    fn from(status: &CameraStatus) -> Self {
+        Self {
+            camera_id: status.camera_id.clone(),
+            name: status.name.clone(),
+            state: status.state.to_string(),
+            running: status.running,
+            backend: status.backend.clone(),
+            current_segment: status.current_segment.clone(),
+            segments_closed: status.segments_closed,
+            last_frame_time: status.last_frame_at.map(|at| {
+                DateTime::<Local>::from(SystemTime::now() - at.elapsed()).to_rfc3339()
+            }),
+            restarts: status.restarts,
+            last_error: status.last_error.as_ref().map(|error| error.to_string()),
+            stopping: status.stopping,
+        }
+    }
+}
+
+/// Running control server
+pub struct ControlServer {
+    server: HttpServer,
+}
+
+impl ControlServer {
+    /// Listen on `bind` and serve requests until `shutdown` is triggered or `stop` is called
+    pub fn start(
+        bind: &str,
+        manager: Arc<Mutex<RecorderManager>>,
+        shutdown: ShutdownSignal,
+    ) -> io::Result<Self> {
+        let signal = shutdown.clone();
+        let server = HttpServer::start(bind, "Control API", shutdown, move |request| {
+            handle_request(request, &manager, &signal)
+        })?;
+        Ok(Self { server })
+    }
+
+    /// Address the server is listening on (resolves port 0)
+    pub fn addr(&self) -> &str {
+        self.server.addr()
+    }
+
+    pub fn stop(&mut self) {
+        self.server.stop();
+    }
+}
+
+fn handle_request(
+    request: Request,
+    manager: &Arc<Mutex<RecorderManager>>,
+    shutdown: &ShutdownSignal,
+) {
+    let method = request.method().clone();
+    let url = request.url().split('?').next().unwrap_or_default().to_string();
+    let segments: Vec<&str> = url.trim_matches('/').split('/').collect();
+
+    let response = match (&method, segments.as_slice()) {
+        (Method::Get, ["cameras"]) => {
+            let statuses: Vec<StatusResponse> = manager
+                .lock()
+                .unwrap()
+                .statuses()
+                .iter()
+                .map(StatusResponse::from)
+                .collect();
+            json_response(200, &statuses)
+        }
+        (Method::Get, ["cameras", id]) => match manager.lock().unwrap().status(id) {
+            Some(status) => json_response(200, &StatusResponse::from(&status)),
+            None => error_response(404, &format!("Unknown camera {}", id)),
+        },
+        (Method::Get, ["cameras", id, "snapshot"]) => {
+            let camera_dir = manager.lock().unwrap().camera_dir(id);
+            match camera_dir.as_deref().and_then(latest_snapshot) {
+                Some(path) => match fs::read(&path) {
+                    Ok(data) => Response::from_data(data)
+                        .with_header(content_type("image/jpeg"))
+                        .with_status_code(200),
+                    Err(e) => {
+                        error_response(500, &format!("Failed to read {}: {}", path.display(), e))
+                    }
+                },
+                None => error_response(404, &format!("No snapshot for camera {}", id)),
+            }
+        }
+        (Method::Post, ["cameras", id, action]) => {
+            let result = match *action {
+                "start" => manager.lock().unwrap().start_camera(id).map(|_| 200),
+                "stop" | "restart" => {
+                    // Stopping a camera that already stopped is not an error
+                    let stopping = manager.lock().unwrap().begin_stop_camera(id);
+                    let restart = *action == "restart";
+                    match stopping {
+                        Some(camera) if camera.is_finished() => {
+                            camera.wait();
+                            if restart {
+                                manager.lock().unwrap().start_camera(id).map(|_| 200)
+                            } else {
+                                Ok(200)
+                            }
+                        }
+                        Some(camera) => {
+                            // Finalizing the segment can take seconds; finish the stop
+                            // off the request thread and report it through the status
+                            finish_stop(camera, restart, Arc::clone(manager), shutdown.clone());
+                            Ok(202)
+                        }
+                        None => Err(format!("Unknown camera {}", id)),
+                    }
+                }
+                _ => Err(format!("Unknown action {}", action)),
+            };
+            let manager = manager.lock().unwrap();
+            match result {
+                Ok(code) => match manager.status(id) {
+                    Some(status) => json_response(code, &StatusResponse::from(&status)),
+                    None => json_response(code, &serde_json::json!({ "camera_id": id })),
+                },
+                Err(e) if e.starts_with("Unknown") => error_response(404, &e),
+                Err(e) => error_response(409, &e),
+            }
+        }
+        _ => error_response(404, "Not found"),
+    };
+
+    if let Err(e) = request.respond(response) {
+        eprintln!("Control API failed to respond: {}", e);
+    }
+}
+
+/// Wait for a stopping camera on a background thread and start it again for a
+/// restart, unless the recorder is shutting down by then
+fn finish_stop(
+    camera: StoppingCamera,
+    restart: bool,
+    manager: Arc<Mutex<RecorderManager>>,
+    shutdown: ShutdownSignal,
+) {
+    thread::spawn(move || {
+        let camera_id = camera.camera_id.clone();
+        camera.wait();
+        if !restart {
+            return;
+        }
+        let mut manager = manager.lock().unwrap();
+        if shutdown.is_triggered() {
+            return;
+        }
+        if let Err(e) = manager.start_camera(&camera_id) {
+            eprintln!("Control API failed to restart {}: {}", camera_id, e);
+        }
+    });
+}
+
+/// Newest snapshot of a camera: `latest.jpg`, or the newest timestamped one
+pub fn latest_snapshot(camera_dir: &Path) -> Option<PathBuf> {
+    let latest = camera_dir.join(LATEST_SNAPSHOT_NAME);
+    if latest.is_file() {
+        return Some(latest);
+    }
+
+    fs::read_dir(camera_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| is_snapshot_file(&name.to_string_lossy()))
+        })
+        .max()
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes("Content-Type", value).unwrap()
+}
+
+fn json_response<T: Serialize>(status: u16, body: &T) -> Response<io::Cursor<Vec<u8>>> {
+    let body = serde_json::to_vec(body).unwrap_or_default();
+    Response::from_data(body)
+        .with_header(content_type("application/json"))
+        .with_status_code(status)
+}
+
+fn error_response(status: u16, message: &str) -> Response<io::Cursor<Vec<u8>>> {
+    json_response(status, &serde_json::json!({ "error": message }))
+}
@@ -0,0 +1,62 @@
+use std::error::Error;
+use std::fmt;
+
+/// Capture error types recognised from FFmpeg output
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaptureError {
+    Unauthorized(String),
+    ConnectionRefused(String),
+    StreamNotFound(String),
+    Timeout(String),
+    /// A segment, playlist or snapshot could not be written
+    Output(String),
+    ProcessFailed(String),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            CaptureError::ConnectionRefused(msg) => write!(f, "Connection refused: {}", msg),
+            CaptureError::StreamNotFound(msg) => write!(f, "Stream not found: {}", msg),
+            CaptureError::Timeout(msg) => write!(f, "Timeout: {}", msg),
+            CaptureError::Output(msg) => write!(f, "Output error: {}", msg),
+            CaptureError::ProcessFailed(msg) => write!(f, "Process failed: {}", msg),
+        }
+    }
+}
+
+impl Error for CaptureError {}
+
+impl CaptureError {
+    /// Classify a single line of FFmpeg output into a known failure.
+    ///
+    /// `input` is the redacted input URL: a missing file is only a missing
+    /// stream when the line names the input, otherwise an output path is
+    /// missing (e.g. the output directory was removed).
+    pub fn from_ffmpeg_line(line: &str, input: &str) -> Option<Self> {
+        let lower = line.to_lowercase();
+        let msg = line.trim().to_string();
+
+        if lower.contains("401 unauthorized") || lower.contains("403 forbidden") {
+            Some(CaptureError::Unauthorized(msg))
+        } else if lower.contains("connection refused")
+            || lower.contains("no route to host")
+            || lower.contains("network is unreachable")
+        {
+            Some(CaptureError::ConnectionRefused(msg))
+        } else if lower.contains("404 not found") || lower.contains("454 session not found") {
+            Some(CaptureError::StreamNotFound(msg))
+        } else if lower.contains("no such file or directory") {
+            if !input.is_empty() && line.contains(input) {
+                Some(CaptureError::StreamNotFound(msg))
+            } else {
+                Some(CaptureError::Output(msg))
+            }
+        } else if lower.contains("timed out") || lower.contains("timeout") {
+            Some(CaptureError::Timeout(msg))
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! FFmpeg recorder backend
+//!
+//! Copies the stream into segments with an FFmpeg child process. FFmpeg cuts
+//! the segments itself; finished ones are found by watching the camera
+//! directory with a [`SegmentTracker`].
+
+use opencv::Result;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::rtsp::backend::{BackendHealth, BackendStats, RecorderBackend};
+use crate::rtsp::credentials;
+use crate::rtsp::error::CaptureError;
+use crate::rtsp::ffmpeg_log::{self, FfmpegLog};
+use crate::rtsp::hls::{self, HlsEventPlaylist};
+use crate::rtsp::overlay;
+use crate::rtsp::segments::{ClosedSegment, SegmentTracker};
+use crate::rtsp::snapshot;
+use crate::rtsp::{OutputFormat, RTSPCapture};
+
+/// Time FFmpeg is given to finalize its segment after 'q' before being killed
+const FFMPEG_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Interval between checks of the running process
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+pub struct FfmpegBackend {
+    process: Option<Child>,
+    log: Option<FfmpegLog>,
+    tracker: Option<SegmentTracker>,
+    event_playlist: Option<HlsEventPlaylist>,
+    segments_closed: u64,
+    /// Newest segment already reported as started
+    announced: Option<PathBuf>,
+    /// Frames written by earlier FFmpeg processes of this backend
+    frames_before: u64,
+    frames_written: u64,
+    last_frame_at: Option<Instant>,
+}
+
+impl FfmpegBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the FFmpeg command for the capture's output settings
+    fn build_command(&mut self, capture: &mut RTSPCapture, camera_dir: &Path) -> Command {
+        let audio_args = capture.ffmpeg_audio_args();
+        let input_url = capture.input_url();
+
+        let mut command = Command::new("ffmpeg");
+        command.args([
+            "-y",
+            "-loglevel",
+            "error", // Reduce log noise
+            "-nostats",
+            "-progress",
+            "pipe:1", // Frame counts for the status
+            "-use_wallclock_as_timestamps",
+            "1", // Use system clock for timestamps
+        ]);
+        command.args(capture.protocol.input_args(capture.transport));
+
+        // An overlay is only burned in when re-encoding is allowed
+        let burn_in = capture.overlay.as_ref().filter(|overlay| overlay.reencode);
+        if capture.snapshots.is_some() && burn_in.is_none() {
+            // Snapshots only need keyframes; the recorded stream is copied untouched
+            command.args(["-skip_frame", "nokey"]);
+        }
+        command.args(["-i", &input_url]);
+        match burn_in {
+            Some(config) => {
+                command.args(overlay::ffmpeg_video_args(config, &capture.camera_name));
+            }
+            None => {
+                command.args(["-c:v", "copy"]); // Copy video stream directly
+            }
+        }
+        command.args(&audio_args);
+
+        match capture.output_format {
+            OutputFormat::Segments => {
+                command.args(segment_output_args(capture, camera_dir));
+            }
+            OutputFormat::Hls => {
+                if capture.align_segments {
+                    eprintln!(
+                        "Warning: segment alignment is not supported for HLS output of {}",
+                        capture.display_url()
+                    );
+                }
+                command.args(hls::hls_output_args(camera_dir, &capture.hls));
+                if capture.hls.event_playlist && self.event_playlist.is_none() {
+                    self.event_playlist = Some(HlsEventPlaylist::new(camera_dir.to_path_buf()));
+                }
+            }
+        }
+
+        // Snapshots are a second output of the same process
+        if let Some(snapshots) = &capture.snapshots {
+            command.args(snapshot::snapshot_output_args(camera_dir, snapshots));
+        }
+
+        command
+    }
+
+    /// Collect the output of an exited FFmpeg process and return why it failed
+    fn finish_log(&mut self, capture: &RTSPCapture, failed: bool) -> Option<CaptureError> {
+        let mut log = self.log.take()?;
+        log.finish();
+        self.frames_before += log.frames();
+        self.frames_written = self.frames_before;
+
+        if !failed {
+            return None;
+        }
+
+        let tail = log.tail();
+        let error = log.last_error().unwrap_or_else(|| {
+            CaptureError::ProcessFailed(
+                tail.last()
+                    .cloned()
+                    .unwrap_or_else(|| "no output from FFmpeg".to_string()),
+            )
+        });
+        eprintln!("FFmpeg for {} failed: {}", capture.display_url(), error);
+        if !tail.is_empty() {
+            eprintln!("Last FFmpeg output for {}:\n  {}", capture.display_url(), tail.join("\n  "));
+        }
+        Some(error)
+    }
+
+    fn update_event_playlist(&mut self, capture: &RTSPCapture) {
+        if let Some(event_playlist) = &mut self.event_playlist {
+            if let Err(e) = event_playlist.update() {
+                eprintln!("Failed to update HLS event playlist for {}: {}", capture.display_url(), e);
+            }
+        }
+    }
+
+    /// Hand the segments FFmpeg has closed to the capture.
+    ///
+    /// With `flush` the newest segment counts as closed too (FFmpeg has exited).
+    fn collect_closed_segments(&mut self, capture: &mut RTSPCapture, flush: bool) {
+        let Some(tracker) = &mut self.tracker else {
+            return;
+        };
+        let closed = if flush { tracker.flush() } else { tracker.poll() };
+        for path in closed {
+            self.segments_closed += 1;
+            capture.on_segment_closed(ClosedSegment::from_path(path));
+        }
+
+        let current = tracker.current().map(Path::to_path_buf);
+        if current.is_some() && current != self.announced {
+            if let Some(path) = &current {
+                capture.on_segment_started(path);
+            }
+            self.announced = current;
+        }
+    }
+}
+
+impl RecorderBackend for FfmpegBackend {
+    fn name(&self) -> &str {
+        "ffmpeg"
+    }
+
+    fn start(&mut self, capture: &mut RTSPCapture) -> Result<()> {
+        // Create camera-specific output directory
+        let camera_dir = capture.camera_dir();
+        fs::create_dir_all(&camera_dir).map_err(|e| {
+            opencv::Error::new(
+                opencv::core::StsError,
+                &format!("Failed to create directory: {}", e),
+            )
+        })?;
+
+        // Segments are found by watching the directory. HLS segments outside an
+        // event playlist are deleted as the window moves on.
+        let persistent = capture.output_format == OutputFormat::Segments || capture.hls.event_playlist;
+        if persistent && self.tracker.is_none() {
+            self.tracker = Some(SegmentTracker::new(&camera_dir));
+        }
+
+        let mut command = self.build_command(capture, &camera_dir);
+
+        // Log the command without the credentials of the input URL
+        let logged_args: Vec<String> = command
+            .get_args()
+            .map(|arg| credentials::redact_text(&arg.to_string_lossy()))
+            .collect();
+        println!("Starting FFmpeg with command: ffmpeg {}", logged_args.join(" "));
+
+        // Start FFmpeg process with proper buffer handling
+        let mut process = command
+            .stdin(Stdio::piped()) // Used to send 'q' on shutdown
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                opencv::Error::new(
+                    opencv::core::StsError,
+                    &format!("Failed to start FFmpeg: {}", e),
+                )
+            })?;
+
+        // Drain stdout/stderr so FFmpeg never blocks on a full pipe
+        self.log = Some(FfmpegLog::attach(
+            &capture.camera_name,
+            &capture.display_url(),
+            &mut process,
+            ffmpeg_log::FFMPEG_LOG_TAIL_LINES,
+        ));
+        self.process = Some(process);
+        println!("Successfully started FFmpeg process for {}", capture.display_url());
+        Ok(())
+    }
+
+    fn poll(&mut self, capture: &mut RTSPCapture) -> Result<BackendHealth> {
+        let Some(process) = &mut self.process else {
+            return Ok(BackendHealth::Failed(CaptureError::ProcessFailed(
+                "FFmpeg is not running".to_string(),
+            )));
+        };
+
+        match process.try_wait() {
+            Ok(Some(status)) => {
+                // Process has finished
+                println!(
+                    "FFmpeg process for {} ended with status: {}",
+                    capture.display_url(),
+                    status
+                );
+                self.process = None;
+                let error = self.finish_log(capture, !status.success()).unwrap_or_else(|| {
+                    CaptureError::ProcessFailed(format!("FFmpeg exited with {}", status))
+                });
+                Ok(BackendHealth::Failed(error))
+            }
+            Ok(None) => {
+                // Process is still running
+                if let Some(log) = &self.log {
+                    let frames = self.frames_before + log.frames();
+                    if frames > self.frames_written {
+                        self.frames_written = frames;
+                        self.last_frame_at = Some(Instant::now());
+                    }
+                }
+                self.update_event_playlist(capture);
+                self.collect_closed_segments(capture, false);
+                capture.shutdown.sleep(POLL_INTERVAL);
+                Ok(BackendHealth::Running)
+            }
+            Err(e) => {
+                eprintln!("Error checking FFmpeg process for {}: {}", capture.display_url(), e);
+                if let Some(mut process) = self.process.take() {
+                    let _ = process.kill();
+                    let _ = process.wait();
+                }
+                let error = self
+                    .finish_log(capture, true)
+                    .unwrap_or_else(|| CaptureError::ProcessFailed(e.to_string()));
+                Ok(BackendHealth::Failed(error))
+            }
+        }
+    }
+
+    /// FFmpeg cuts segments on its own; an explicit rotation restarts the process
+    fn rotate(&mut self, capture: &mut RTSPCapture) -> Result<()> {
+        self.stop(capture)?;
+        self.start(capture)
+    }
+
+    /// Ask FFmpeg to quit so it can finalize the open segment, killing it if it hangs
+    fn stop(&mut self, capture: &mut RTSPCapture) -> Result<()> {
+        if let Some(mut process) = self.process.take() {
+            // 'q' on stdin is FFmpeg's interactive quit command
+            if let Some(mut stdin) = process.stdin.take() {
+                let _ = stdin.write_all(b"q");
+                let _ = stdin.flush();
+            }
+
+            let deadline = Instant::now() + FFMPEG_STOP_TIMEOUT;
+            let mut finalized = false;
+            while !finalized {
+                match process.try_wait() {
+                    Ok(Some(status)) => {
+                        println!("FFmpeg for {} finalized with status: {}", capture.display_url(), status);
+                        finalized = true;
+                    }
+                    Ok(None) if Instant::now() < deadline => {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    _ => break,
+                }
+            }
+
+            if !finalized {
+                eprintln!("FFmpeg for {} did not exit in time, killing it", capture.display_url());
+                let _ = process.kill();
+                let _ = process.wait();
+            }
+        }
+        self.finish_log(capture, false);
+
+        self.update_event_playlist(capture);
+        self.collect_closed_segments(capture, true);
+        // The next process continues the event playlist after a gap
+        if let Some(event_playlist) = &mut self.event_playlist {
+            event_playlist.mark_discontinuity();
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> BackendStats {
+        BackendStats {
+            current_segment: self
+                .tracker
+                .as_ref()
+                .and_then(|tracker| tracker.current())
+                .map(Path::to_path_buf),
+            segments_closed: self.segments_closed,
+            segments_untracked: self.tracker.is_none(),
+            frames_written: self.frames_written,
+            last_frame_at: self.last_frame_at,
+        }
+    }
+}
+
+/// Build the FFmpeg output arguments for fixed-length MP4 segments
+pub fn segment_output_args(capture: &RTSPCapture, camera_dir: &Path) -> Vec<String> {
+    let output_pattern = camera_dir
+        .join("segment_%Y%m%d_%H%M%S.mp4")
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    [
+        "-f",
+        "segment",
+        "-segment_time",
+        &capture.segment_duration.as_secs().to_string(),
+        "-segment_format",
+        "mp4",
+        "-reset_timestamps",
+        "1",
+        "-segment_format_options",
+        "movflags=+faststart+frag_keyframe+empty_moov+default_base_moof",
+        "-segment_time_delta",
+        "0.05", // Small delta to handle rounding
+        "-segment_atclocktime",
+        if capture.align_segments { "1" } else { "0" }, // Cut at wall-clock boundaries
+        "-strftime",
+        "1",
+        &output_pattern,
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect()
+}
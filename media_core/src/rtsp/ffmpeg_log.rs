@@ -0,0 +1,143 @@
+//! Draining of FFmpeg stdout/stderr
+//!
+//! Each FFmpeg child gets one reader thread per pipe so a chatty process can
+//! never block on a full pipe buffer. Lines are forwarded to our logs, the
+//! last few are kept for error reports and known failures are classified
+//! into a [`CaptureError`]. `-progress pipe:1` reports on stdout are parsed
+//! for the frame count instead of being logged.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::process::Child;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::rtsp::credentials::redact_text;
+use crate::rtsp::error::CaptureError;
+
+/// Number of output lines kept for error reports
+pub const FFMPEG_LOG_TAIL_LINES: usize = 50;
+
+pub struct FfmpegLog {
+    tail: Arc<Mutex<VecDeque<String>>>,
+    last_error: Arc<Mutex<Option<CaptureError>>>,
+    frames: Arc<AtomicU64>,
+    readers: Vec<thread::JoinHandle<()>>,
+}
+
+impl FfmpegLog {
+    /// Take the child's stdout/stderr pipes and start draining them.
+    /// `input` is the redacted input URL, used to classify failures.
+    pub fn attach(label: &str, input: &str, child: &mut Child, capacity: usize) -> Self {
+        let mut log = Self {
+            tail: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            last_error: Arc::new(Mutex::new(None)),
+            frames: Arc::new(AtomicU64::new(0)),
+            readers: Vec::new(),
+        };
+
+        if let Some(stdout) = child.stdout.take() {
+            log.spawn_reader(format!("[ffmpeg {}]", label), input, stdout, false, capacity);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            log.spawn_reader(format!("[ffmpeg {}]", label), input, stderr, true, capacity);
+        }
+
+        log
+    }
+
+    fn spawn_reader<R: Read + Send + 'static>(
+        &mut self,
+        prefix: String,
+        input: &str,
+        pipe: R,
+        is_stderr: bool,
+        capacity: usize,
+    ) {
+        let tail = Arc::clone(&self.tail);
+        let last_error = Arc::clone(&self.last_error);
+        let frames = Arc::clone(&self.frames);
+        let input = input.to_string();
+
+        let handle = thread::spawn(move || {
+            let mut reader = BufReader::new(pipe);
+            let mut buffer = Vec::new();
+            loop {
+                buffer.clear();
+                match reader.read_until(b'\n', &mut buffer) {
+                    Ok(0) | Err(_) => break, // Pipe closed
+                    Ok(_) => {}
+                }
+
+                // FFmpeg echoes the input URL in many messages
+                let line = redact_text(String::from_utf8_lossy(&buffer).trim_end());
+                if line.is_empty() {
+                    continue;
+                }
+
+                // Outputs go to files, so stdout only carries progress reports
+                if !is_stderr {
+                    if let Some((key, value)) = progress_entry(&line) {
+                        if key == "frame" {
+                            if let Ok(count) = value.parse() {
+                                frames.store(count, Ordering::Relaxed);
+                            }
+                        }
+                        continue;
+                    }
+                }
+
+                if is_stderr {
+                    eprintln!("{} {}", prefix, line);
+                } else {
+                    println!("{} {}", prefix, line);
+                }
+
+                if let Some(error) = CaptureError::from_ffmpeg_line(&line, &input) {
+                    *last_error.lock().unwrap() = Some(error);
+                }
+
+                let mut tail = tail.lock().unwrap();
+                while tail.len() >= capacity.max(1) {
+                    tail.pop_front();
+                }
+                tail.push_back(line);
+            }
+        });
+
+        self.readers.push(handle);
+    }
+
+    /// Last lines written by FFmpeg, oldest first
+    pub fn tail(&self) -> Vec<String> {
+        self.tail.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Most recent known failure seen in the output
+    pub fn last_error(&self) -> Option<CaptureError> {
+        self.last_error.lock().unwrap().clone()
+    }
+
+    /// Frames written so far according to FFmpeg's progress reports
+    pub fn frames(&self) -> u64 {
+        self.frames.load(Ordering::Relaxed)
+    }
+
+    /// Wait for the reader threads after the process has exited
+    pub fn finish(&mut self) {
+        for handle in self.readers.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Split a `-progress` report line such as `frame=250` into key and value
+pub fn progress_entry(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once('=')?;
+    let is_key = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    is_key.then_some((key, value.trim()))
+}
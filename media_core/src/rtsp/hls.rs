@@ -0,0 +1,242 @@
+//! HLS live output for RTSP capture
+//!
+//! FFmpeg's `hls` muxer writes the rolling live playlist and its segments.
+//! The optional event playlist keeps every segment of the current day and is
+//! finalized as a VOD playlist when the day rolls over.
+
+use chrono::Local;
+use m3u8_rs::{MediaPlaylist, MediaPlaylistType, MediaSegment};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the rolling live playlist inside a camera directory
+pub const LIVE_PLAYLIST_NAME: &str = "live.m3u8";
+
+/// Container used for HLS media segments
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HlsSegmentType {
+    #[default]
+    Mpegts,
+    Fmp4,
+}
+
+impl HlsSegmentType {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            HlsSegmentType::Mpegts => "ts",
+            HlsSegmentType::Fmp4 => "m4s",
+        }
+    }
+}
+
+/// HLS output settings
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HlsConfig {
+    #[serde(default)]
+    pub segment_type: HlsSegmentType,
+    /// Target duration of each HLS segment in seconds
+    #[serde(default = "default_hls_segment_duration")]
+    pub segment_duration: u64,
+    /// Number of segments kept in the live playlist
+    #[serde(default = "default_hls_window")]
+    pub window: usize,
+    /// Keep a per-day event playlist (finalized as VOD at midnight)
+    #[serde(default)]
+    pub event_playlist: bool,
+}
+
+fn default_hls_segment_duration() -> u64 {
+    4
+}
+
+fn default_hls_window() -> usize {
+    6
+}
+
+impl Default for HlsConfig {
+    fn default() -> Self {
+        Self {
+            segment_type: HlsSegmentType::default(),
+            segment_duration: default_hls_segment_duration(),
+            window: default_hls_window(),
+            event_playlist: false,
+        }
+    }
+}
+
+/// Build the FFmpeg output arguments for HLS into `camera_dir`
+pub fn hls_output_args(camera_dir: &Path, config: &HlsConfig) -> Vec<String> {
+    let segment_pattern = camera_dir.join(format!(
+        "hls_%Y%m%d_%H%M%S.{}",
+        config.segment_type.extension()
+    ));
+    let playlist = camera_dir.join(LIVE_PLAYLIST_NAME);
+
+    // Segments must outlive the live window when the event playlist references them
+    let mut flags = vec!["program_date_time", "independent_segments"];
+    if !config.event_playlist {
+        flags.push("delete_segments");
+    }
+
+    let mut args: Vec<String> = vec![
+        "-f".into(),
+        "hls".into(),
+        "-hls_time".into(),
+        config.segment_duration.to_string(),
+        "-hls_list_size".into(),
+        config.window.to_string(),
+        "-hls_flags".into(),
+        flags.join("+"),
+    ];
+
+    match config.segment_type {
+        HlsSegmentType::Mpegts => {
+            args.extend(["-hls_segment_type".to_string(), "mpegts".to_string()]);
+        }
+        HlsSegmentType::Fmp4 => {
+            args.extend([
+                "-hls_segment_type".to_string(),
+                "fmp4".to_string(),
+                "-hls_fmp4_init_filename".to_string(),
+                "init.mp4".to_string(),
+            ]);
+        }
+    }
+
+    args.extend([
+        "-strftime".to_string(),
+        "1".to_string(),
+        "-hls_segment_filename".to_string(),
+        segment_pattern.to_string_lossy().to_string(),
+        playlist.to_string_lossy().to_string(),
+    ]);
+
+    args
+}
+
+/// Per-day event playlist built from the rolling live playlist
+pub struct HlsEventPlaylist {
+    camera_dir: PathBuf,
+    day: String,
+    playlist: MediaPlaylist,
+    seen: HashSet<String>,
+    pending_discontinuity: bool,
+}
+
+impl HlsEventPlaylist {
+    pub fn new(camera_dir: PathBuf) -> Self {
+        let day = Local::now().format("%Y%m%d").to_string();
+        let mut event = Self {
+            camera_dir,
+            day: String::new(),
+            playlist: MediaPlaylist::default(),
+            seen: HashSet::new(),
+            pending_discontinuity: false,
+        };
+        event.open_day(day);
+        event
+    }
+
+    /// Path of the event playlist for a given day (`YYYYMMDD`)
+    pub fn playlist_path(&self, day: &str) -> PathBuf {
+        self.camera_dir.join(format!("event_{}.m3u8", day))
+    }
+
+    /// Mark the next appended segment as a discontinuity (e.g. after an FFmpeg restart)
+    pub fn mark_discontinuity(&mut self) {
+        if !self.playlist.segments.is_empty() {
+            self.pending_discontinuity = true;
+        }
+    }
+
+    /// Append segments that appeared in the live playlist since the last update
+    pub fn update(&mut self) -> std::io::Result<()> {
+        let today = Local::now().format("%Y%m%d").to_string();
+        if today != self.day {
+            self.finalize()?;
+            self.open_day(today);
+        }
+
+        let live_path = self.camera_dir.join(LIVE_PLAYLIST_NAME);
+        let data = match fs::read(&live_path) {
+            Ok(data) => data,
+            Err(_) => return Ok(()), // FFmpeg has not written the playlist yet
+        };
+        let live = match m3u8_rs::parse_media_playlist_res(&data) {
+            Ok(live) => live,
+            Err(_) => return Ok(()), // Partially written playlist, retry on next update
+        };
+
+        let mut changed = false;
+        for segment in live.segments {
+            if self.seen.contains(&segment.uri) {
+                continue;
+            }
+            self.append_segment(segment);
+            changed = true;
+        }
+
+        if changed {
+            self.write()?;
+        }
+        Ok(())
+    }
+
+    /// Close the current day's playlist as VOD
+    pub fn finalize(&mut self) -> std::io::Result<()> {
+        if self.playlist.segments.is_empty() {
+            return Ok(());
+        }
+        self.playlist.playlist_type = Some(MediaPlaylistType::Vod);
+        self.playlist.end_list = true;
+        self.write()
+    }
+
+    fn append_segment(&mut self, mut segment: MediaSegment) {
+        if self.pending_discontinuity {
+            segment.discontinuity = true;
+            self.pending_discontinuity = false;
+        }
+        let duration = segment.duration.ceil();
+        if duration > self.playlist.target_duration {
+            self.playlist.target_duration = duration;
+        }
+        self.seen.insert(segment.uri.clone());
+        self.playlist.segments.push(segment);
+    }
+
+    fn open_day(&mut self, day: String) {
+        let existing = fs::read(self.playlist_path(&day))
+            .ok()
+            .and_then(|data| m3u8_rs::parse_media_playlist_res(&data).ok());
+
+        self.playlist = match existing {
+            // Continue today's playlist after a restart
+            Some(playlist) if !playlist.end_list => playlist,
+            _ => MediaPlaylist {
+                version: Some(7),
+                playlist_type: Some(MediaPlaylistType::Event),
+                ..Default::default()
+            },
+        };
+        self.seen = self
+            .playlist
+            .segments
+            .iter()
+            .map(|segment| segment.uri.clone())
+            .collect();
+        self.pending_discontinuity = !self.playlist.segments.is_empty();
+        self.day = day;
+    }
+
+    fn write(&self) -> std::io::Result<()> {
+        let path = self.playlist_path(&self.day);
+        let tmp_path = path.with_extension("m3u8.tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        self.playlist.write_to(&mut file)?;
+        fs::rename(&tmp_path, &path)
+    }
+}
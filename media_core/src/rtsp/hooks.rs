@@ -0,0 +1,261 @@
+//! Segment completion hooks
+//!
+//! Every finished segment can run an external command and/or POST its
+//! catalog entry as JSON to a webhook. Hooks run on their own thread behind a
+//! bounded queue: when a consumer falls behind, new segments are dropped from
+//! the queue (and logged) instead of stalling the capture. When the capture
+//! stops, queued hooks get `drain_timeout_secs` to run; the rest are dropped.
+
+use serde::{Deserialize, Serialize};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::rtsp::catalog::CatalogEntry;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HooksConfig {
+    /// Program and arguments to run; the segment path is appended as last argument
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+    /// URL receiving the segment's catalog entry as a JSON POST
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    /// Segments waiting for their hooks before new ones are dropped
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+    /// Retries after a failed command or webhook call
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+    /// Time a command or webhook call may take before it counts as failed
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Time queued hooks may still run once the capture stops
+    #[serde(default = "default_drain_timeout_secs")]
+    pub drain_timeout_secs: u64,
+}
+
+fn default_queue_size() -> usize {
+    100
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_retry_delay_ms() -> u64 {
+    2000
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+fn default_drain_timeout_secs() -> u64 {
+    10
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            command: None,
+            webhook_url: None,
+            queue_size: default_queue_size(),
+            max_retries: default_max_retries(),
+            retry_delay_ms: default_retry_delay_ms(),
+            timeout_secs: default_timeout_secs(),
+            drain_timeout_secs: default_drain_timeout_secs(),
+        }
+    }
+}
+
+/// Runs the hooks of one camera on a background thread
+pub struct HookDispatcher {
+    sender: Option<SyncSender<CatalogEntry>>,
+    handle: Option<thread::JoinHandle<()>>,
+    /// Set by `finish`; hooks still queued after it are dropped
+    deadline: Arc<OnceLock<Instant>>,
+    drain_timeout: Duration,
+}
+
+impl HookDispatcher {
+    pub fn spawn(config: HooksConfig) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<CatalogEntry>(config.queue_size.max(1));
+        let deadline = Arc::new(OnceLock::new());
+        let drain_timeout = Duration::from_secs(config.drain_timeout_secs);
+
+        let worker_deadline = Arc::clone(&deadline);
+        let handle = thread::spawn(move || {
+            let agent = ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(config.timeout_secs.max(1)))
+                .build();
+
+            let mut dropped = 0;
+            for entry in receiver {
+                if is_past(&worker_deadline) {
+                    dropped += 1;
+                    continue;
+                }
+                if let Some(command) = &config.command {
+                    with_retries(&config, &worker_deadline, "command", &entry, || {
+                        run_command(command, &entry, &config)
+                    });
+                }
+                if let Some(url) = &config.webhook_url {
+                    with_retries(&config, &worker_deadline, "webhook", &entry, || {
+                        post_webhook(&agent, url, &entry)
+                    });
+                }
+            }
+            if dropped > 0 {
+                eprintln!("Hooks did not finish in time, dropped hooks for {} segment(s)", dropped);
+            }
+        });
+
+        Self {
+            sender: Some(sender),
+            handle: Some(handle),
+            deadline,
+            drain_timeout,
+        }
+    }
+
+    /// Queue a finished segment without blocking
+    pub fn dispatch(&self, entry: CatalogEntry) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        match sender.try_send(entry) {
+            Ok(_) => {}
+            Err(TrySendError::Full(entry)) => eprintln!(
+                "Hook queue full, dropping hooks for segment {}",
+                entry.path
+            ),
+            Err(TrySendError::Disconnected(entry)) => eprintln!(
+                "Hook thread stopped, dropping hooks for segment {}",
+                entry.path
+            ),
+        }
+    }
+
+    /// Run the queued hooks for up to `drain_timeout_secs` and stop the thread.
+    /// Hooks not started by then are dropped; a hook still running is left to
+    /// finish on its own.
+    pub fn finish(&mut self) {
+        self.sender.take();
+        let deadline = Instant::now() + self.drain_timeout;
+        let _ = self.deadline.set(deadline);
+        let Some(handle) = self.handle.take() else {
+            return;
+        };
+
+        while !handle.is_finished() {
+            if Instant::now() >= deadline {
+                eprintln!("Hooks did not finish in time, not waiting for the running hook");
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        let _ = handle.join();
+    }
+}
+
+/// Whether `finish` was called and its drain deadline has passed
+fn is_past(deadline: &OnceLock<Instant>) -> bool {
+    deadline.get().is_some_and(|deadline| Instant::now() >= *deadline)
+}
+
+/// Run `hook` until it succeeds, the retries are used up or the drain deadline passes
+fn with_retries<F>(
+    config: &HooksConfig,
+    deadline: &OnceLock<Instant>,
+    kind: &str,
+    entry: &CatalogEntry,
+    mut hook: F,
+) where
+    F: FnMut() -> Result<(), String>,
+{
+    for attempt in 0..=config.max_retries {
+        if attempt > 0 && is_past(deadline) {
+            return;
+        }
+        match hook() {
+            Ok(_) => return,
+            Err(e) => {
+                eprintln!(
+                    "Segment {} {} failed (attempt {}/{}): {}",
+                    kind,
+                    entry.path,
+                    attempt + 1,
+                    config.max_retries + 1,
+                    e
+                );
+                if attempt < config.max_retries {
+                    thread::sleep(Duration::from_millis(config.retry_delay_ms));
+                }
+            }
+        }
+    }
+}
+
+/// Environment passed to hook commands
+pub fn hook_env(entry: &CatalogEntry) -> Vec<(&'static str, String)> {
+    vec![
+        ("CAMERA_ID", entry.camera_id.clone()),
+        ("SEGMENT_PATH", entry.path.clone()),
+        ("SEGMENT_START", entry.start_time.clone()),
+        ("SEGMENT_END", entry.end_time.clone()),
+        ("SEGMENT_DURATION", entry.duration_secs.to_string()),
+        ("SEGMENT_SIZE", entry.size_bytes.to_string()),
+        ("SEGMENT_CHECKSUM", entry.checksum.clone()),
+        (
+            "SEGMENT_JSON",
+            serde_json::to_string(entry).unwrap_or_default(),
+        ),
+    ]
+}
+
+/// Run the hook command for a segment, killing it after the timeout
+pub fn run_command(command: &[String], entry: &CatalogEntry, config: &HooksConfig) -> Result<(), String> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| "empty hook command".to_string())?;
+
+    let mut child = Command::new(program)
+        .args(args)
+        .arg(&entry.path)
+        .envs(hook_env(entry))
+        .stdin(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("failed to start {}: {}", program, e))?;
+
+    let deadline = Instant::now() + Duration::from_secs(config.timeout_secs.max(1));
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("{} exited with {}", program, status)),
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(100)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("{} timed out", program));
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+/// POST the segment's catalog entry to the webhook
+pub fn post_webhook(agent: &ureq::Agent, url: &str, entry: &CatalogEntry) -> Result<(), String> {
+    let body = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+    agent
+        .post(url)
+        .set("Content-Type", "application/json")
+        .send_string(&body)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,81 @@
+//! Background HTTP server
+//!
+//! The serve loop shared by the control API and the metrics endpoint: a
+//! `tiny_http` server on its own thread that hands every request to a handler
+//! and checks for shutdown while idle.
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Request, Server};
+
+use crate::rtsp::shutdown::ShutdownSignal;
+
+/// Interval at which the server checks for shutdown while idle
+const RECV_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Running HTTP server thread
+pub struct HttpServer {
+    addr: String,
+    shutdown: ShutdownSignal,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl HttpServer {
+    /// Listen on `bind` and pass requests to `handler` until `shutdown` is
+    /// triggered or `stop` is called. `name` prefixes the logged errors.
+    pub fn start<F>(
+        bind: &str,
+        name: &'static str,
+        shutdown: ShutdownSignal,
+        mut handler: F,
+    ) -> io::Result<Self>
+    where
+        F: FnMut(Request) + Send + 'static,
+    {
+        let server = Server::http(bind).map_err(|e| io::Error::other(e.to_string()))?;
+        let addr = match server.server_addr().to_ip() {
+            Some(addr) => addr.to_string(),
+            None => bind.to_string(),
+        };
+        let shutdown = shutdown.child();
+        let signal = shutdown.clone();
+
+        let handle = thread::spawn(move || {
+            while !signal.is_triggered() {
+                match server.recv_timeout(RECV_TIMEOUT) {
+                    Ok(Some(request)) => handler(request),
+                    Ok(None) => {}
+                    Err(e) => {
+                        eprintln!("{} error: {}", name, e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            addr,
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    /// Address the server is listening on (resolves port 0)
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub fn stop(&mut self) {
+        self.shutdown.trigger();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,498 @@
+//! Recording many cameras from a host application
+//!
+//! [`RecorderManager`] runs every camera's [`RTSPCapture`] on its own thread,
+//! lets cameras be added and removed while the others keep recording, and
+//! reports what happens as [`RecorderEvent`]s on a channel. Each camera can be
+//! stopped and queried through a cloneable [`CameraHandle`].
+//!
+//! Stopping a camera waits for it to finalize its segment, which can take
+//! seconds. A manager shared behind a mutex should use the `begin_*` methods,
+//! which return [`StoppingCamera`]s to wait for after releasing the lock.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::rtsp::backend::RecorderBackend;
+use crate::rtsp::camera::CameraConfig;
+use crate::rtsp::error::CaptureError;
+use crate::rtsp::reconnect::ConnectionState;
+use crate::rtsp::reload::{self, ConfigDiff};
+use crate::rtsp::shutdown::ShutdownSignal;
+use crate::rtsp::{CaptureConfig, CaptureSummary, RTSPCapture};
+
+/// Something that happened to a camera
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecorderEvent {
+    SegmentStarted { camera_id: String, path: PathBuf },
+    SegmentClosed { camera_id: String, path: PathBuf },
+    /// First stable connection
+    Connected { camera_id: String },
+    /// A connected camera stopped delivering; the capture is reconnecting
+    Disconnected { camera_id: String, error: Option<CaptureError> },
+    /// Stable again after a disconnect
+    Reconnected { camera_id: String },
+    /// The capture thread ended, with the error that ended it
+    Stopped { camera_id: String, error: Option<String> },
+}
+
+/// Snapshot of a camera's recording state
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraStatus {
+    pub camera_id: String,
+    pub name: String,
+    pub state: ConnectionState,
+    /// Name of the backend recording the camera
+    pub backend: String,
+    pub current_segment: Option<PathBuf>,
+    pub segments_closed: u64,
+    /// Whether the backend does not report finished segments
+    pub segments_untracked: bool,
+    /// Size of the closed segments
+    pub bytes_written: u64,
+    pub last_segment_at: Option<Instant>,
+    pub frames_written: u64,
+    pub last_frame_at: Option<Instant>,
+    /// Frames per second measured over the last few seconds
+    pub fps: Option<f64>,
+    pub restarts: u32,
+    /// Failed attempts since the camera was last connected
+    pub consecutive_failures: u32,
+    pub last_error: Option<CaptureError>,
+    /// Whether the capture thread is still alive
+    pub running: bool,
+    /// Whether the camera was asked to stop and is finalizing its segment
+    pub stopping: bool,
+}
+
+impl CameraStatus {
+    pub fn new(camera_id: &str, name: &str) -> Self {
+        Self {
+            camera_id: camera_id.to_string(),
+            name: name.to_string(),
+            state: ConnectionState::Connecting,
+            backend: String::new(),
+            current_segment: None,
+            segments_closed: 0,
+            segments_untracked: false,
+            bytes_written: 0,
+            last_segment_at: None,
+            frames_written: 0,
+            last_frame_at: None,
+            fps: None,
+            restarts: 0,
+            consecutive_failures: 0,
+            last_error: None,
+            running: true,
+            stopping: false,
+        }
+    }
+}
+
+/// Stop and query one managed camera
+#[derive(Clone, Debug)]
+pub struct CameraHandle {
+    pub camera_id: String,
+    shutdown: ShutdownSignal,
+    status: Arc<Mutex<CameraStatus>>,
+}
+
+impl CameraHandle {
+    /// Ask the camera to stop; it finalizes its open segment first
+    pub fn stop(&self) {
+        let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        status.stopping = status.running;
+        drop(status);
+        self.shutdown.trigger();
+    }
+
+    pub fn status(&self) -> CameraStatus {
+        self.status.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.status.lock().unwrap_or_else(|e| e.into_inner()).running
+    }
+}
+
+/// Marks the camera as stopped when its capture thread ends, even by panicking
+struct RunningGuard {
+    camera_id: String,
+    status: Arc<Mutex<CameraStatus>>,
+    events: Sender<RecorderEvent>,
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        // The status lock is poisoned if the capture panicked while holding it
+        let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        status.running = false;
+        status.stopping = false;
+        drop(status);
+        if thread::panicking() {
+            let _ = self.events.send(RecorderEvent::Stopped {
+                camera_id: self.camera_id.clone(),
+                error: Some("capture thread panicked".to_string()),
+            });
+        }
+    }
+}
+
+struct ManagedCamera {
+    camera: CameraConfig,
+    handle: CameraHandle,
+    thread: Option<thread::JoinHandle<(CaptureSummary, bool)>>,
+}
+
+/// A camera asked to stop, whose capture thread may still be finalizing
+pub struct StoppingCamera {
+    pub camera_id: String,
+    thread: Option<thread::JoinHandle<(CaptureSummary, bool)>>,
+}
+
+impl StoppingCamera {
+    /// Whether the capture thread already ended, so `wait` returns at once
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(|thread| thread.is_finished())
+    }
+
+    /// Wait for the capture thread; `None` if it was already reaped or panicked
+    pub fn wait(mut self) -> Option<(CaptureSummary, bool)> {
+        match self.thread.take()?.join() {
+            Ok(summary) => Some(summary),
+            Err(_) => {
+                eprintln!("❌ Capture thread of {} panicked", self.camera_id);
+                None
+            }
+        }
+    }
+}
+
+/// Owns the capture threads of many cameras
+pub struct RecorderManager {
+    config: CaptureConfig,
+    shutdown: ShutdownSignal,
+    events: Sender<RecorderEvent>,
+    cameras: HashMap<String, ManagedCamera>,
+}
+
+impl RecorderManager {
+    /// Manager applying the global settings of `config` to the cameras added later.
+    ///
+    /// Triggering `shutdown` stops every camera. The receiver gets the events of
+    /// all cameras; dropping it simply discards them.
+    pub fn new(config: CaptureConfig, shutdown: ShutdownSignal) -> (Self, Receiver<RecorderEvent>) {
+        let (events, receiver) = mpsc::channel();
+        let manager = Self {
+            config,
+            shutdown,
+            events,
+            cameras: HashMap::new(),
+        };
+        (manager, receiver)
+    }
+
+    pub fn config(&self) -> &CaptureConfig {
+        &self.config
+    }
+
+    /// Start recording a camera with the backend its configuration selects
+    pub fn add_camera(&mut self, camera: CameraConfig) -> Result<CameraHandle, String> {
+        self.spawn_camera(camera, None)
+    }
+
+    /// Start recording a camera with a custom backend
+    pub fn add_camera_with_backend(
+        &mut self,
+        camera: CameraConfig,
+        backend: Box<dyn RecorderBackend>,
+    ) -> Result<CameraHandle, String> {
+        self.spawn_camera(camera, Some(backend))
+    }
+
+    fn spawn_camera(
+        &mut self,
+        camera: CameraConfig,
+        backend: Option<Box<dyn RecorderBackend>>,
+    ) -> Result<CameraHandle, String> {
+        if self.cameras.contains_key(&camera.id) {
+            return Err(format!("Camera {} is already recording", camera.id));
+        }
+        camera.validate()?;
+
+        let mut capture = RTSPCapture::from_camera(&camera, &self.config, self.config.preview_enabled())
+            .map_err(|e| format!("Failed to create capture for {}: {}", camera.display_name(), e.message))?;
+        capture.shutdown = self.shutdown.child();
+        capture.events = Some(self.events.clone());
+        capture.backend = backend;
+
+        let handle = CameraHandle {
+            camera_id: camera.id.clone(),
+            shutdown: capture.shutdown.clone(),
+            status: Arc::clone(&capture.status),
+        };
+
+        let camera_id = camera.id.clone();
+        let events = self.events.clone();
+        let status = Arc::clone(&capture.status);
+        let thread = thread::spawn(move || {
+            let running = RunningGuard {
+                camera_id: camera_id.clone(),
+                status,
+                events: events.clone(),
+            };
+            println!("📹 Processing stream: {} ({})", capture.camera_name, capture.display_url());
+            let result = capture.process_stream();
+            if let Err(e) = &result {
+                eprintln!("❌ Error processing stream {}: {:?}", capture.camera_name, e);
+            }
+            drop(running);
+            let _ = events.send(RecorderEvent::Stopped {
+                camera_id,
+                error: result.as_ref().err().map(|e| e.message.clone()),
+            });
+            (capture.summary(), result.is_ok())
+        });
+
+        self.cameras.insert(
+            camera.id.clone(),
+            ManagedCamera {
+                camera,
+                handle: handle.clone(),
+                thread: Some(thread),
+            },
+        );
+        Ok(handle)
+    }
+
+    /// Stop a camera and wait until its last segment is finalized
+    pub fn remove_camera(&mut self, camera_id: &str) -> Option<(CaptureSummary, bool)> {
+        self.begin_remove_camera(camera_id)?.wait()
+    }
+
+    /// Ask a camera to stop and forget it, without waiting for it
+    pub fn begin_remove_camera(&mut self, camera_id: &str) -> Option<StoppingCamera> {
+        let mut managed = self.cameras.remove(camera_id)?;
+        managed.handle.stop();
+        Some(StoppingCamera {
+            camera_id: camera_id.to_string(),
+            thread: managed.thread.take(),
+        })
+    }
+
+    /// Stop a camera but keep it listed, so it can be started again
+    pub fn stop_camera(&mut self, camera_id: &str) -> Option<(CaptureSummary, bool)> {
+        self.begin_stop_camera(camera_id)?.wait()
+    }
+
+    /// Ask a camera to stop but keep it listed, without waiting for it.
+    /// `None` only for unknown cameras; a camera that already stopped gives a
+    /// `StoppingCamera` with nothing left to wait for.
+    pub fn begin_stop_camera(&mut self, camera_id: &str) -> Option<StoppingCamera> {
+        let managed = self.cameras.get_mut(camera_id)?;
+        managed.handle.stop();
+        Some(StoppingCamera {
+            camera_id: camera_id.to_string(),
+            thread: managed.thread.take(),
+        })
+    }
+
+    /// Start a stopped camera, or a configured camera that is not recording
+    /// (e.g. a disabled one). Restarted cameras use their configured backend.
+    /// A camera still finalizing after a stop counts as recording.
+    pub fn start_camera(&mut self, camera_id: &str) -> Result<CameraHandle, String> {
+        let camera = match self.cameras.get(camera_id) {
+            Some(managed) if managed.handle.is_running() => {
+                return Err(format!("Camera {} is already recording", camera_id));
+            }
+            Some(managed) => managed.camera.clone(),
+            None => self
+                .config
+                .resolved_cameras()
+                .into_iter()
+                .find(|camera| camera.id == camera_id)
+                .ok_or_else(|| format!("Unknown camera {}", camera_id))?,
+        };
+
+        // Reap the finished thread before starting over
+        self.remove_camera(camera_id);
+        self.add_camera(camera)
+    }
+
+    /// Stop a camera, finalizing its segment, and start it again
+    pub fn restart_camera(&mut self, camera_id: &str) -> Result<CameraHandle, String> {
+        if !self.cameras.contains_key(camera_id) {
+            return Err(format!("Unknown camera {}", camera_id));
+        }
+        self.stop_camera(camera_id);
+        self.start_camera(camera_id)
+    }
+
+    /// Output directory of a managed camera
+    pub fn camera_dir(&self, camera_id: &str) -> Option<PathBuf> {
+        self.camera(camera_id)
+            .map(|camera| PathBuf::from(&self.config.output_directory).join(camera.directory_name()))
+    }
+
+    pub fn handle(&self, camera_id: &str) -> Option<CameraHandle> {
+        self.cameras.get(camera_id).map(|managed| managed.handle.clone())
+    }
+
+    /// Configuration a managed camera was started with
+    pub fn camera(&self, camera_id: &str) -> Option<&CameraConfig> {
+        self.cameras.get(camera_id).map(|managed| &managed.camera)
+    }
+
+    pub fn status(&self, camera_id: &str) -> Option<CameraStatus> {
+        self.cameras.get(camera_id).map(|managed| managed.handle.status())
+    }
+
+    /// Status of every camera, ordered by id
+    pub fn statuses(&self) -> Vec<CameraStatus> {
+        let mut statuses: Vec<CameraStatus> = self
+            .cameras
+            .values()
+            .map(|managed| managed.handle.status())
+            .collect();
+        statuses.sort_by(|a, b| a.camera_id.cmp(&b.camera_id));
+        statuses
+    }
+
+    /// Whether there are cameras and all of them stopped (e.g. gave up reconnecting)
+    pub fn all_stopped(&self) -> bool {
+        !self.cameras.is_empty() && self.cameras.values().all(|managed| !managed.handle.is_running())
+    }
+
+    pub fn camera_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.cameras.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Wait for every camera thread to end (after shutdown or on their own)
+    pub fn join_all(&mut self) -> Vec<(CaptureSummary, bool)> {
+        self.take_all()
+            .into_iter()
+            .filter_map(StoppingCamera::wait)
+            .collect()
+    }
+
+    /// Forget every camera, returning their threads to wait for, ordered by id
+    pub fn take_all(&mut self) -> Vec<StoppingCamera> {
+        self.camera_ids()
+            .into_iter()
+            .filter_map(|camera_id| {
+                let mut managed = self.cameras.remove(&camera_id)?;
+                Some(StoppingCamera {
+                    camera_id,
+                    thread: managed.thread.take(),
+                })
+            })
+            .collect()
+    }
+
+    /// Switch to a new configuration, touching only the cameras that differ.
+    ///
+    /// Added cameras start, removed ones stop after finalizing their segment
+    /// and changed ones restart; all others keep recording without a gap.
+    pub fn apply_config(&mut self, config: CaptureConfig) -> ConfigDiff {
+        let (diff, stopping) = self.begin_apply_config(config);
+        for camera in stopping {
+            camera.wait();
+        }
+        self.finish_apply_config(&diff);
+        diff
+    }
+
+    /// First half of [`apply_config`](Self::apply_config): switch to the new
+    /// configuration and ask removed and changed cameras to stop. Wait for the
+    /// returned cameras, then call `finish_apply_config`.
+    pub fn begin_apply_config(&mut self, config: CaptureConfig) -> (ConfigDiff, Vec<StoppingCamera>) {
+        let running: Vec<CameraConfig> = self
+            .cameras
+            .values()
+            .map(|managed| managed.camera.clone())
+            .collect();
+        let diff = reload::diff_cameras(&running, &self.config, &config);
+
+        if self.config.retention != config.retention
+            || self.config.upload != config.upload
+            || self.config.control != config.control
+            || self.config.metrics != config.metrics
+        {
+            eprintln!(
+                "Warning: retention, upload, control API and metrics changes take effect after a restart"
+            );
+        }
+        if self.config.hooks != config.hooks
+            || self.config.catalog != config.catalog
+            || self.config.verify_on_startup != config.verify_on_startup
+        {
+            eprintln!(
+                "Warning: hooks, catalog and verify_on_startup changes apply to cameras as they (re)start"
+            );
+        }
+        self.config = config;
+
+        // Everything that goes away stops at once so the cameras finalize in parallel
+        let stopping: Vec<StoppingCamera> = diff
+            .removed
+            .iter()
+            .chain(diff.changed.iter().map(|camera| &camera.id))
+            .filter_map(|camera_id| self.begin_remove_camera(camera_id))
+            .collect();
+        (diff, stopping)
+    }
+
+    /// Second half of [`apply_config`](Self::apply_config): start the added
+    /// cameras and the changed ones again
+    pub fn finish_apply_config(&mut self, diff: &ConfigDiff) {
+        for camera in diff.changed.iter().chain(diff.added.iter()) {
+            if let Err(e) = self.add_camera(camera.clone()) {
+                eprintln!("❌ {}", e);
+            }
+        }
+    }
+
+    /// Stop every camera and wait for them
+    pub fn stop_all(&mut self) -> Vec<(CaptureSummary, bool)> {
+        for managed in self.cameras.values() {
+            managed.handle.stop();
+        }
+        self.join_all()
+    }
+}
+
+impl Drop for RecorderManager {
+    fn drop(&mut self) {
+        self.stop_all();
+    }
+}
+
+/// Stop a shared manager and the background workers (retention, upload) that
+/// run until `shutdown` is triggered.
+///
+/// Triggers `shutdown` even when every camera already stopped on its own, waits
+/// for the cameras to finalize without holding the manager lock, then joins
+/// `workers`. The cameras stay in the manager, reported as running, until they
+/// have finalized, so workers can tell when their last segments are complete.
+/// Returns the summaries of the cameras, ordered by id.
+pub fn shutdown_recorder(
+    manager: &Mutex<RecorderManager>,
+    shutdown: &ShutdownSignal,
+    workers: Vec<thread::JoinHandle<()>>,
+) -> Vec<(CaptureSummary, bool)> {
+    shutdown.trigger();
+    while manager.lock().unwrap().statuses().iter().any(|status| status.running) {
+        thread::sleep(Duration::from_millis(50));
+    }
+    let stopping = manager.lock().unwrap().take_all();
+    let summaries = stopping.into_iter().filter_map(StoppingCamera::wait).collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    summaries
+}
@@ -1,43 +1,303 @@
-use chrono::Local;
-use opencv::{prelude::*, videoio, Result};
+use chrono::{DateTime, TimeZone, Timelike};
+use opencv::Result;
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::PathBuf;
-use std::process::{Child, Command, Stdio};
-use std::thread;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-#[derive(Serialize, Deserialize, Debug)]
+use crate::metrics::MetricsConfig;
+
+pub mod backend;
+pub mod camera;
+pub mod catalog;
+pub mod control;
+pub mod credentials;
+pub mod error;
+pub mod ffmpeg_backend;
+pub mod ffmpeg_log;
+pub mod hls;
+pub mod hooks;
+pub mod http_server;
+pub mod manager;
+pub mod motion;
+pub mod opencv_backend;
+pub mod overlay;
+pub mod probe;
+pub mod protocol;
+pub mod reconnect;
+pub mod reload;
+pub mod retention;
+pub mod segments;
+pub mod shutdown;
+pub mod snapshot;
+pub mod upload;
+pub mod verify;
+
+#[cfg(test)]
+mod tests;
+
+pub use backend::{BackendHealth, BackendKind, BackendStats, FakeBackend, RecorderBackend};
+pub use camera::{CameraConfig, CameraSource, RtspTransport};
+pub use catalog::{Catalog, CatalogEntry};
+pub use control::{ControlConfig, ControlServer};
+pub use credentials::{Credentials, CredentialsConfig};
+pub use error::CaptureError;
+pub use ffmpeg_backend::FfmpegBackend;
+pub use ffmpeg_log::FfmpegLog;
+pub use hls::{HlsConfig, HlsEventPlaylist, HlsSegmentType};
+pub use hooks::{HookDispatcher, HooksConfig};
+pub use http_server::HttpServer;
+pub use manager::{shutdown_recorder, CameraHandle, CameraStatus, RecorderEvent, RecorderManager, StoppingCamera};
+pub use motion::{MotionConfig, MotionEvent, MotionGate, MotionState, PreRollBuffer};
+pub use opencv_backend::OpenCvBackend;
+pub use overlay::{OverlayConfig, OverlayPosition};
+pub use probe::{AudioTrack, StreamReport};
+pub use protocol::InputProtocol;
+pub use reconnect::{Backoff, ConnectionState, ReconnectPolicy};
+pub use reload::{ConfigDiff, ConfigWatcher};
+pub use retention::{RetentionManager, RetentionPolicy, RetentionReport};
+pub use segments::{ClosedSegment, SegmentTracker, SegmentWorker};
+pub use shutdown::ShutdownSignal;
+pub use snapshot::{SnapshotConfig, SnapshotMode, SnapshotWriter};
+pub use upload::{UploadConfig, UploadReport, Uploader};
+pub use verify::{DecodeCheck, SegmentIssue, VerifyAction, VerifyReport};
+
+/// Time a backend must keep recording before the camera counts as connected
+const STABLE_CONNECTION_TIME: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum SavingOption {
+    #[default]
     Single,
     List,
     Both,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// How the audio track is written when audio recording is enabled
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioCodec {
+    /// Copy the source track when MP4 can hold it, otherwise transcode to AAC
+    #[default]
+    Auto,
+    Copy,
+    Aac,
+}
+
+/// Output written by the FFmpeg recorder
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Fixed-length `segment_*.mp4` files
+    #[default]
+    Segments,
+    /// Rolling HLS playlist with `.ts` or fMP4 segments
+    Hls,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct CaptureConfig {
+    /// Per-camera configuration, takes precedence over the legacy URL fields
+    #[serde(default)]
+    pub cameras: Vec<CameraConfig>,
+    #[serde(default)]
     pub rtsp_url: String,
+    #[serde(default)]
     pub rtsp_url_list: Vec<String>,
     pub output_directory: String,
+    #[serde(default)]
     pub show_preview: bool,
+    #[serde(default)]
     pub saving_option: SavingOption,
     pub saved_time_duration: u64,
+    #[serde(default)]
+    pub audio: bool,
+    #[serde(default)]
+    pub audio_codec: AudioCodec,
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    #[serde(default)]
+    pub hls: HlsConfig,
+    #[serde(default)]
+    pub retention: Option<RetentionPolicy>,
+    /// Ship closed segments to S3-compatible object storage
+    #[serde(default)]
+    pub upload: Option<UploadConfig>,
+    /// Local HTTP API reporting camera status and starting/stopping cameras
+    #[serde(default)]
+    pub control: Option<ControlConfig>,
+    /// Prometheus `/metrics` endpoint with per-camera counters
+    #[serde(default)]
+    pub metrics: Option<MetricsConfig>,
+    #[serde(default)]
+    pub reconnect: ReconnectPolicy,
+    /// Cut segments at wall-clock multiples of the segment duration (e.g. :00, :10, :20)
+    #[serde(default)]
+    pub align_segments: bool,
+    /// Seconds without a decoded frame before the OpenCV path reconnects
+    #[serde(default = "default_stall_timeout_secs")]
+    pub stall_timeout_secs: u64,
+    /// Record only while motion is detected (decodes frames like the OpenCV path)
+    #[serde(default)]
+    pub motion: Option<MotionConfig>,
+    /// Periodic JPEG snapshots in every camera directory
+    #[serde(default)]
+    pub snapshots: Option<SnapshotConfig>,
+    /// Timestamp and camera name burned into the recordings
+    #[serde(default)]
+    pub overlay: Option<OverlayConfig>,
+    /// Command and/or webhook run for every finished segment
+    #[serde(default)]
+    pub hooks: Option<HooksConfig>,
+    /// Switch RTSP cameras using UDP to TCP when an attempt receives no packets
+    #[serde(default = "default_transport_fallback")]
+    pub transport_fallback: bool,
+    /// Check (and repair) the newest segment of every camera before recording
+    #[serde(default = "default_verify_on_startup")]
+    pub verify_on_startup: bool,
+    /// Keep a `catalog.jsonl` index of finished segments in every camera directory
+    #[serde(default = "default_catalog")]
+    pub catalog: bool,
+    /// JSON file mapping camera ids to credentials
+    #[serde(default)]
+    pub secrets_file: Option<String>,
+    #[serde(default)]
     pub use_fps: bool,
+    #[serde(default = "default_fps")]
     pub fps: f64,
+    /// Recorder backend for every camera; by default OpenCV is used when
+    /// `use_fps` or `motion` need decoded frames and FFmpeg otherwise
+    #[serde(default)]
+    pub backend: Option<BackendKind>,
+}
+
+fn default_fps() -> f64 {
+    30.0
+}
+
+fn default_stall_timeout_secs() -> u64 {
+    10
+}
+
+fn default_catalog() -> bool {
+    true
+}
+
+fn default_verify_on_startup() -> bool {
+    true
+}
+
+fn default_transport_fallback() -> bool {
+    true
+}
+
+impl CaptureConfig {
+    /// Cameras to record, built from `cameras` or from the legacy URL fields
+    pub fn resolved_cameras(&self) -> Vec<CameraConfig> {
+        if !self.cameras.is_empty() {
+            return self.cameras.clone();
+        }
+
+        let urls: Vec<&String> = match self.saving_option {
+            SavingOption::Single => vec![&self.rtsp_url],
+            SavingOption::List => self.rtsp_url_list.iter().collect(),
+            SavingOption::Both => std::iter::once(&self.rtsp_url)
+                .chain(self.rtsp_url_list.iter())
+                .collect(),
+        };
+
+        urls.into_iter()
+            .filter(|url| !url.is_empty())
+            .map(|url| CameraConfig::from_legacy_url(url))
+            .collect()
+    }
+
+    /// Credentials for a camera from its environment variables or the secrets file
+    pub fn camera_credentials(&self, camera: &CameraConfig) -> std::io::Result<Option<Credentials>> {
+        let secrets = match &self.secrets_file {
+            Some(path) => Some(credentials::load_secrets(path)?),
+            None => None,
+        };
+        Ok(credentials::resolve_credentials(
+            &camera.id,
+            camera.credentials.as_ref(),
+            secrets.as_ref(),
+        ))
+    }
+
+    /// Whether the preview window is shown, which needs exactly one enabled camera
+    pub fn preview_enabled(&self) -> bool {
+        let single_camera = if self.cameras.is_empty() {
+            matches!(self.saving_option, SavingOption::Single)
+        } else {
+            self.cameras.iter().filter(|camera| camera.enabled).count() == 1
+        };
+        self.show_preview && single_camera
+    }
 }
 
 pub struct RTSPCapture {
+    pub camera_id: String,
+    pub camera_name: String,
+    pub camera_subdir: String,
     pub url: String,
+    pub protocol: InputProtocol,
+    pub credentials: Option<Credentials>,
+    pub transport: RtspTransport,
+    /// Switch from UDP to TCP after an attempt without packets
+    pub transport_fallback: bool,
     pub output_dir: String,
     pub show_preview: bool,
-    pub capture: Option<videoio::VideoCapture>,
-    pub writer: Option<videoio::VideoWriter>,
-    pub ffmpeg_process: Option<Child>,
-    pub current_file_start: Instant,
+    /// Backend recording this camera; picked from `backend_kind` when empty
+    pub backend: Option<Box<dyn RecorderBackend>>,
+    pub backend_kind: Option<BackendKind>,
+    pub last_error: Option<CaptureError>,
     pub segment_duration: Duration,
+    pub align_segments: bool,
     pub use_custom_fps: bool,
     pub custom_fps: f64,
+    pub audio: bool,
+    pub audio_codec: AudioCodec,
+    pub audio_source_codec: Option<String>,
+    pub output_format: OutputFormat,
+    pub hls: HlsConfig,
+    pub shutdown: ShutdownSignal,
+    pub started_at: Instant,
+    pub restarts: u32,
+    /// Failed attempts since the last stable connection
+    pub consecutive_failures: u32,
+    pub reconnect: ReconnectPolicy,
+    pub state: ConnectionState,
+    pub stall_timeout: Duration,
+    pub motion: Option<MotionConfig>,
+    pub snapshots: Option<SnapshotConfig>,
+    pub overlay: Option<OverlayConfig>,
+    pub verify_on_startup: bool,
+    pub catalog: bool,
+    pub hooks: Option<HooksConfig>,
+    pub segment_worker: Option<SegmentWorker>,
+    /// Receives segment and connection events when set
+    pub events: Option<Sender<RecorderEvent>>,
+    /// Status shared with `CameraHandle`s, updated while recording
+    pub status: Arc<Mutex<CameraStatus>>,
+    /// Whether the camera has been connected since recording started
+    pub connected_once: bool,
+    /// Start of the current frame rate window and the frame count at that time
+    fps_sample: Option<(Instant, u64)>,
+}
+
+/// Window over which the frame rate in the camera status is measured
+const FPS_WINDOW: Duration = Duration::from_secs(5);
+
+/// Summary of a capture thread reported on exit
+#[derive(Debug, Clone)]
+pub struct CaptureSummary {
+    pub camera: String,
+    pub uptime: Duration,
+    pub restarts: u32,
 }
 
 impl RTSPCapture {
@@ -49,328 +309,525 @@ impl RTSPCapture {
         use_custom_fps: bool,
         custom_fps: f64,
     ) -> Result<Self> {
+        let camera = CameraConfig::from_legacy_url(&url);
         Ok(Self {
+            camera_id: camera.id.clone(),
+            camera_name: camera.display_name().to_string(),
+            camera_subdir: camera.directory_name(),
+            protocol: InputProtocol::detect(&url),
             url,
+            credentials: None,
+            transport: camera.transport,
+            transport_fallback: default_transport_fallback(),
             output_dir,
             show_preview,
-            capture: None,
-            writer: None,
-            ffmpeg_process: None,
-            current_file_start: Instant::now(),
+            backend: None,
+            backend_kind: None,
+            last_error: None,
             segment_duration: Duration::from_secs(segment_duration_secs),
+            align_segments: false,
             use_custom_fps,
             custom_fps,
+            audio: false,
+            audio_codec: AudioCodec::Auto,
+            audio_source_codec: None,
+            output_format: OutputFormat::Segments,
+            hls: HlsConfig::default(),
+            shutdown: ShutdownSignal::new(),
+            started_at: Instant::now(),
+            restarts: 0,
+            consecutive_failures: 0,
+            reconnect: ReconnectPolicy::default(),
+            state: ConnectionState::Connecting,
+            stall_timeout: Duration::from_secs(default_stall_timeout_secs()),
+            motion: None,
+            snapshots: None,
+            overlay: None,
+            verify_on_startup: default_verify_on_startup(),
+            catalog: default_catalog(),
+            hooks: None,
+            segment_worker: None,
+            events: None,
+            status: Arc::new(Mutex::new(CameraStatus::new(&camera.id, camera.display_name()))),
+            connected_once: false,
+            fps_sample: None,
         })
     }
 
-    pub fn start_ffmpeg_recording(&mut self) -> std::io::Result<()> {
-        // Create camera-specific output directory
-        let camera_dir = PathBuf::from(&self.output_dir).join(format!(
-            "camera_{}",
-            self.url
-                .replace("://", "_")
-                .replace("/", "_")
-                .replace(":", "_")
-        ));
-        fs::create_dir_all(&camera_dir)?;
-
-        // Prepare FFmpeg command
-        let output_pattern = camera_dir
-            .join("segment_%Y%m%d_%H%M%S.mp4")
-            .to_str()
-            .unwrap()
-            .to_string();
-
-        let mut command = Command::new("ffmpeg");
-        command.args([
-            "-y",
-            "-loglevel",
-            "error", // Reduce log noise
-            "-rtsp_transport",
-            "tcp",
-            "-use_wallclock_as_timestamps",
-            "1", // Use system clock for timestamps
-            "-i",
-            &self.url,
-            "-c:v",
-            "copy", // Copy video stream directly
-            "-an",  // Remove audio
-            "-f",
-            "segment",
-            "-segment_time",
-            &self.segment_duration.as_secs().to_string(),
-            "-segment_format",
-            "mp4",
-            "-reset_timestamps",
-            "1",
-            "-segment_format_options",
-            "movflags=+faststart+frag_keyframe+empty_moov+default_base_moof",
-            "-segment_time_delta",
-            "0.05", // Small delta to handle rounding
-            "-strftime",
-            "1",
-            "-reconnect_at_eof",
-            "1", // Reconnect if stream ends
-            "-reconnect_streamed",
-            "1", // Reconnect if stream fails
-            "-reconnect_delay_max",
-            "120", // Maximum reconnection delay
-            &output_pattern,
-        ]);
-
-        println!("Starting FFmpeg with command: {:?}", command);
-
-        // Start FFmpeg process with proper buffer handling
-        let process = command
-            .stdin(Stdio::null()) // Don't need stdin
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
-
-        self.ffmpeg_process = Some(process);
-        Ok(())
+    /// Create a capture for one configured camera, applying the global settings
+    /// of `config` where the camera does not override them
+    pub fn from_camera(camera: &CameraConfig, config: &CaptureConfig, show_preview: bool) -> Result<Self> {
+        let (use_custom_fps, custom_fps) = match camera.fps {
+            Some(fps) => (true, fps),
+            None => (config.use_fps, config.fps),
+        };
+
+        let mut capture = Self::new(
+            camera.url.clone(),
+            config.output_directory.clone(),
+            show_preview,
+            camera.segment_duration.unwrap_or(config.saved_time_duration),
+            use_custom_fps,
+            custom_fps,
+        )?;
+        capture.camera_id = camera.id.clone();
+        capture.camera_name = camera.display_name().to_string();
+        capture.camera_subdir = camera.directory_name();
+        capture.status = Arc::new(Mutex::new(CameraStatus::new(&camera.id, camera.display_name())));
+        capture.protocol = camera.protocol.unwrap_or_else(|| InputProtocol::detect(&camera.url));
+        capture.transport = camera.transport;
+        capture.transport_fallback = config.transport_fallback;
+        capture.credentials = config.camera_credentials(camera).map_err(|e| {
+            opencv::Error::new(
+                opencv::core::StsError,
+                &format!("Failed to load credentials: {}", e),
+            )
+        })?;
+        capture.reconnect = camera
+            .reconnect
+            .clone()
+            .unwrap_or_else(|| config.reconnect.clone());
+        capture.align_segments = config.align_segments;
+        capture.stall_timeout = Duration::from_secs(config.stall_timeout_secs.max(1));
+        capture.motion = camera.motion.clone().or_else(|| config.motion.clone());
+        capture.snapshots = camera.snapshots.clone().or_else(|| config.snapshots.clone());
+        capture.overlay = camera.overlay.clone().or_else(|| config.overlay.clone());
+        capture.backend_kind = camera.backend.or(config.backend);
+        capture.verify_on_startup = config.verify_on_startup;
+        capture.catalog = config.catalog;
+        capture.hooks = config.hooks.clone();
+        capture.audio = camera.audio.unwrap_or(config.audio);
+        capture.audio_codec = camera.audio_codec.unwrap_or(config.audio_codec);
+        capture.output_format = config.output_format;
+        capture.hls = config.hls.clone();
+        Ok(capture)
     }
 
-    pub fn start_opencv_recording(&mut self) -> Result<()> {
-        // Create capture with FFMPEG backend for better control
-        let mut capture = videoio::VideoCapture::from_file(&self.url, videoio::CAP_FFMPEG)?;
+    /// Whether `other` records the same stream the same way, so a running
+    /// capture can keep going instead of restarting for it. The startup check,
+    /// the catalog and hooks are not compared.
+    pub fn same_recording_settings(&self, other: &RTSPCapture) -> bool {
+        self.camera_id == other.camera_id
+            && self.camera_name == other.camera_name
+            && self.camera_subdir == other.camera_subdir
+            && self.url == other.url
+            && self.protocol == other.protocol
+            && self.credentials == other.credentials
+            && self.transport == other.transport
+            && self.transport_fallback == other.transport_fallback
+            && self.output_dir == other.output_dir
+            && self.show_preview == other.show_preview
+            && self.backend_kind == other.backend_kind
+            && self.segment_duration == other.segment_duration
+            && self.align_segments == other.align_segments
+            && self.use_custom_fps == other.use_custom_fps
+            && self.custom_fps == other.custom_fps
+            && self.audio == other.audio
+            && self.audio_codec == other.audio_codec
+            && self.output_format == other.output_format
+            && self.hls == other.hls
+            && self.reconnect == other.reconnect
+            && self.stall_timeout == other.stall_timeout
+            && self.motion == other.motion
+            && self.snapshots == other.snapshots
+            && self.overlay == other.overlay
+    }
 
-        if !capture.is_opened()? {
-            return Err(opencv::Error::new(
-                opencv::core::StsError,
-                "Failed to open RTSP stream",
-            ));
+    /// Summary of this capture for the shutdown report
+    pub fn summary(&self) -> CaptureSummary {
+        CaptureSummary {
+            camera: self.camera_name.clone(),
+            uptime: self.started_at.elapsed(),
+            restarts: self.restarts,
         }
+    }
 
-        // Get the stream's FPS
-        let stream_fps = capture.get(videoio::CAP_PROP_FPS)?;
-        let actual_fps = if stream_fps <= 0.0 {
-            if self.use_custom_fps {
-                self.custom_fps
-            } else {
-                30.0 // Default fallback
+    /// URL handed to FFmpeg/OpenCV, with separately supplied credentials injected
+    pub fn input_url(&self) -> String {
+        match &self.credentials {
+            Some(credentials) => credentials::inject_credentials(&self.url, credentials),
+            None => self.url.clone(),
+        }
+    }
+
+    /// URL without credentials, for logs
+    pub fn display_url(&self) -> String {
+        credentials::redact_url(&self.url)
+    }
+
+    /// Camera-specific output directory
+    pub fn camera_dir(&self) -> PathBuf {
+        PathBuf::from(&self.output_dir).join(&self.camera_subdir)
+    }
+
+    /// Build the FFmpeg audio arguments for the configured audio mode
+    pub fn ffmpeg_audio_args(&mut self) -> Vec<String> {
+        if !self.audio {
+            return vec!["-an".to_string()]; // Remove audio
+        }
+
+        let codec = match self.audio_codec {
+            AudioCodec::Copy => "copy",
+            AudioCodec::Aac => "aac",
+            AudioCodec::Auto => {
+                if self.audio_source_codec.is_none() {
+                    self.audio_source_codec =
+                        probe_audio_codec(&self.input_url(), &self.protocol.probe_args(self.transport));
+                    match &self.audio_source_codec {
+                        Some(codec) => println!("Audio codec for {}: {}", self.display_url(), codec),
+                        None => println!("Could not probe audio codec for {}, transcoding to AAC", self.display_url()),
+                    }
+                }
+                audio_codec_for_mp4(self.audio_source_codec.as_deref())
             }
-        } else {
-            stream_fps
         };
 
-        println!("Stream FPS: {}", actual_fps);
+        vec!["-c:a".to_string(), codec.to_string()]
+    }
 
-        // Optimize for video-only capture
-        let _ = capture.set(videoio::CAP_PROP_CONVERT_RGB, 1.0);
+    pub fn process_stream(&mut self) -> Result<()> {
+        if self.verify_on_startup {
+            // A crash or power loss most likely broke the segment written last
+            let report = verify::check_last_segment(&self.camera_dir());
+            for error in &report.errors {
+                eprintln!("Startup check for {}: {}", self.camera_name, error);
+            }
+        }
+        self.start_segment_worker();
 
-        self.capture = Some(capture);
-        Ok(())
+        let mut backend = match self.backend.take() {
+            Some(backend) => backend,
+            None => self.default_backend(),
+        };
+        println!("Recording {} with the {} backend", self.camera_name, backend.name());
+        let result = self.run_backend(backend.as_mut());
+        self.backend = Some(backend);
+
+        self.finish_segment_worker();
+        result
     }
 
-    pub fn process_stream(&mut self) -> Result<()> {
-        if self.use_custom_fps {
-            // Use OpenCV for custom FPS recording
-            self.start_opencv_recording()?;
-            self.process_stream_opencv()
-        } else {
-            // Use FFmpeg for direct stream copying
-            self.start_ffmpeg_recording().map_err(|e| {
-                opencv::Error::new(
-                    opencv::core::StsError,
-                    &format!("Failed to start FFmpeg: {}", e),
-                )
-            })?;
-            self.process_stream_ffmpeg()
+    /// Built-in backend for this camera: the configured one, otherwise OpenCV
+    /// when custom fps or motion detection need decoded frames
+    pub fn resolved_backend_kind(&self) -> BackendKind {
+        match self.backend_kind {
+            Some(kind) => kind,
+            None if self.use_custom_fps || self.motion.is_some() => BackendKind::Opencv,
+            None => BackendKind::Ffmpeg,
+        }
+    }
+
+    /// Create the built-in backend picked by `resolved_backend_kind`
+    pub fn default_backend(&self) -> Box<dyn RecorderBackend> {
+        match self.resolved_backend_kind() {
+            BackendKind::Ffmpeg => {
+                if self.use_custom_fps || self.motion.is_some() || self.show_preview {
+                    eprintln!(
+                        "Warning: custom fps, motion detection and preview need the OpenCV backend and are ignored for {}",
+                        self.camera_name
+                    );
+                }
+                if self.overlay.as_ref().is_some_and(|overlay| !overlay.reencode) {
+                    eprintln!(
+                        "Warning: the overlay needs `reencode` with the FFmpeg backend and is ignored for {}",
+                        self.camera_name
+                    );
+                }
+                Box::new(FfmpegBackend::new())
+            }
+            BackendKind::Opencv => Box::new(OpenCvBackend::new()),
         }
     }
 
-    pub fn process_stream_ffmpeg(&mut self) -> Result<()> {
-        let mut consecutive_failures = 0;
-        let max_failures = 3; // Maximum number of consecutive failures before longer wait
+    /// Drive a backend until shutdown, restarting it according to the reconnect policy
+    fn run_backend(&mut self, backend: &mut dyn RecorderBackend) -> Result<()> {
+        let mut backoff = Backoff::new(self.reconnect.clone());
+        let mut running = false;
+        let mut started_at = Instant::now();
 
-        loop {
-            if self.ffmpeg_process.is_none() {
-                // Start a new FFmpeg process if none exists
-                match self.start_ffmpeg_recording() {
+        while !self.shutdown.is_triggered() {
+            if !running {
+                self.set_state(ConnectionState::Connecting);
+                match backend.start(self) {
                     Ok(_) => {
-                        println!("Successfully started FFmpeg process for {}", self.url);
-                        consecutive_failures = 0;
+                        running = true;
+                        started_at = Instant::now();
+                        self.publish_status(backend);
                     }
                     Err(e) => {
-                        eprintln!("Failed to start FFmpeg for {}: {}", self.url, e);
-                        consecutive_failures += 1;
-                        if consecutive_failures >= max_failures {
-                            // Wait longer if we've had multiple failures
-                            thread::sleep(Duration::from_secs(10));
-                        } else {
-                            thread::sleep(Duration::from_secs(1));
+                        eprintln!("Failed to start recording {}: {}", self.display_url(), e.message);
+                        self.last_error = Some(CaptureError::ProcessFailed(e.message));
+                        self.consecutive_failures += 1;
+                        self.publish_status(backend);
+                        if !self.wait_before_retry(&mut backoff) {
+                            break;
                         }
                         continue;
                     }
                 }
             }
 
-            if let Some(process) = &mut self.ffmpeg_process {
-                match process.try_wait() {
-                    Ok(Some(status)) => {
-                        // Process has finished
-                        println!(
-                            "FFmpeg process for {} ended with status: {}",
-                            self.url, status
-                        );
-                        if !status.success() {
-                            eprintln!("FFmpeg process failed for {}, restarting...", self.url);
-                            consecutive_failures += 1;
-                        }
-                        self.ffmpeg_process = None;
-
-                        if consecutive_failures >= max_failures {
-                            thread::sleep(Duration::from_secs(10));
-                        } else {
-                            thread::sleep(Duration::from_secs(1));
-                        }
+            let health = backend.poll(self);
+            self.publish_status(backend);
+            match health {
+                Ok(BackendHealth::Running) => {
+                    // Consider the camera connected once the backend keeps running
+                    if self.state != ConnectionState::Connected
+                        && started_at.elapsed() >= STABLE_CONNECTION_TIME
+                    {
+                        self.set_state(ConnectionState::Connected);
+                        self.consecutive_failures = 0;
+                        backoff.reset();
                     }
-                    Ok(None) => {
-                        // Process is still running
-                        consecutive_failures = 0; // Reset failure count while running
-                        thread::sleep(Duration::from_secs(1));
+                }
+                Ok(BackendHealth::Failed(error)) => {
+                    eprintln!("Recording of {} failed ({}), restarting...", self.display_url(), error);
+                    self.last_error = Some(error);
+                    self.restarts += 1;
+                    self.consecutive_failures += 1;
+                    running = false;
+                    let received = backend
+                        .stats()
+                        .last_frame_at
+                        .is_some_and(|at| at >= started_at);
+                    backend.stop(self)?;
+                    if !received {
+                        self.fall_back_to_tcp();
                     }
-                    Err(e) => {
-                        eprintln!("Error checking FFmpeg process for {}: {}", self.url, e);
-                        self.ffmpeg_process = None;
-                        consecutive_failures += 1;
-                        if consecutive_failures >= max_failures {
-                            thread::sleep(Duration::from_secs(10));
-                        } else {
-                            thread::sleep(Duration::from_secs(1));
-                        }
+                    if !self.wait_before_retry(&mut backoff) {
+                        break;
                     }
                 }
+                Ok(BackendHealth::Finished) => break,
+                Err(e) => {
+                    let _ = backend.stop(self);
+                    return Err(e);
+                }
             }
         }
-    }
 
-    pub fn process_stream_opencv(&mut self) -> Result<()> {
-        let window = if self.show_preview {
-            let window_name = format!("RTSP Stream - {}", self.url);
-            opencv::highgui::named_window(&window_name, opencv::highgui::WINDOW_AUTOSIZE)?;
-            Some(window_name)
-        } else {
-            None
-        };
+        if running {
+            // Let the backend finalize the open segment
+            println!("Stopping {} recording for {}", backend.name(), self.display_url());
+            backend.stop(self)?;
+            self.publish_status(backend);
+        }
 
-        let mut frame = Mat::default();
+        if matches!(self.state, ConnectionState::GaveUp { .. }) {
+            return Err(self.gave_up_error());
+        }
+        self.set_state(ConnectionState::Stopped);
+        Ok(())
+    }
 
-        // Create first video file
-        self.create_new_video_file()?;
+    /// Switch an RTSP camera from UDP to TCP after an attempt that received
+    /// nothing, e.g. because a firewall or NAT drops the UDP packets
+    fn fall_back_to_tcp(&mut self) {
+        if self.transport_fallback
+            && self.protocol == InputProtocol::Rtsp
+            && self.transport == RtspTransport::Udp
+        {
+            eprintln!("No packets from {} over UDP, falling back to TCP", self.display_url());
+            self.transport = RtspTransport::Tcp;
+        }
+    }
 
-        loop {
-            let current_time = Instant::now();
-            let segment_elapsed = current_time.duration_since(self.current_file_start);
+    /// Start the background processing of finished segments
+    fn start_segment_worker(&mut self) {
+        let catalog = self.catalog.then(|| Catalog::for_camera_dir(&self.camera_dir()));
+        self.segment_worker = Some(SegmentWorker::spawn(
+            self.camera_id.clone(),
+            catalog,
+            self.hooks.clone(),
+        ));
+    }
 
-            // Check if we need to start a new segment
-            if segment_elapsed >= self.segment_duration {
-                self.create_new_video_file()?;
-                continue;
-            }
+    /// Wait until every finished segment has been processed
+    fn finish_segment_worker(&mut self) {
+        if let Some(mut worker) = self.segment_worker.take() {
+            worker.finish();
+        }
+    }
 
-            // Read new frame if available
-            if let Some(capture) = &mut self.capture {
-                let frame_read = capture.read(&mut frame)?;
+    /// Single entry point for segments a backend has stopped writing to
+    pub fn on_segment_closed(&mut self, segment: ClosedSegment) {
+        let size = std::fs::metadata(&segment.path).map_or(0, |metadata| metadata.len());
+        {
+            let mut status = self.status.lock().unwrap();
+            status.bytes_written += size;
+            status.last_segment_at = Some(Instant::now());
+        }
+        self.emit(RecorderEvent::SegmentClosed {
+            camera_id: self.camera_id.clone(),
+            path: segment.path.clone(),
+        });
+        if let Some(worker) = &self.segment_worker {
+            worker.submit(segment);
+        }
+    }
 
-                if frame_read && !frame.empty() {
-                    // Write frame to file
-                    if let Some(writer) = &mut self.writer {
-                        writer.write(&frame)?;
-                    }
+    /// Called by backends when they begin writing a new segment
+    pub fn on_segment_started(&mut self, path: &Path) {
+        self.emit(RecorderEvent::SegmentStarted {
+            camera_id: self.camera_id.clone(),
+            path: path.to_path_buf(),
+        });
+    }
 
-                    // Show preview window
-                    if let Some(window_name) = &window {
-                        opencv::highgui::imshow(window_name, &frame)?;
-                    }
-                } else {
-                    // End of stream or error, break the loop
-                    break;
-                }
-            } else {
-                break;
-            }
+    fn emit(&self, event: RecorderEvent) {
+        if let Some(events) = &self.events {
+            // A dropped receiver only means nobody is listening
+            let _ = events.send(event);
+        }
+    }
 
-            // Wait for a short duration
-            if self.show_preview {
-                let key = opencv::highgui::wait_key(1)?;
-                if key == 27 {
-                    // ESC key
-                    break;
-                }
-            } else {
-                thread::sleep(Duration::from_millis(10)); // Adjust as needed
+    /// Copy the backend's counters into the shared status
+    fn publish_status(&mut self, backend: &dyn RecorderBackend) {
+        let stats = backend.stats();
+        let now = Instant::now();
+        let fps = match self.fps_sample {
+            Some((since, frames)) if now.duration_since(since) >= FPS_WINDOW => {
+                self.fps_sample = Some((now, stats.frames_written));
+                let elapsed = now.duration_since(since).as_secs_f64();
+                Some(stats.frames_written.saturating_sub(frames) as f64 / elapsed)
+            }
+            Some(_) => None,
+            None => {
+                self.fps_sample = Some((now, stats.frames_written));
+                None
             }
+        };
+
+        let mut status = self.status.lock().unwrap();
+        status.backend = backend.name().to_string();
+        status.current_segment = stats.current_segment;
+        status.segments_closed = stats.segments_closed;
+        status.segments_untracked = stats.segments_untracked;
+        status.frames_written = stats.frames_written;
+        status.last_frame_at = stats.last_frame_at;
+        if fps.is_some() {
+            status.fps = fps;
         }
+        status.restarts = self.restarts;
+        status.consecutive_failures = self.consecutive_failures;
+        status.last_error = self.last_error.clone();
+    }
 
-        if let Some(window_name) = &window {
-            opencv::highgui::destroy_window(window_name)?;
+    /// Record and report a connection state transition
+    pub fn set_state(&mut self, state: ConnectionState) {
+        if self.state == state {
+            return;
         }
+        println!("Camera {} is {}", self.camera_name, state);
 
-        Ok(())
+        let camera_id = self.camera_id.clone();
+        if state == ConnectionState::Connected {
+            self.emit(if self.connected_once {
+                RecorderEvent::Reconnected { camera_id }
+            } else {
+                RecorderEvent::Connected { camera_id }
+            });
+            self.connected_once = true;
+        } else if self.state == ConnectionState::Connected && state != ConnectionState::Stopped {
+            self.emit(RecorderEvent::Disconnected {
+                camera_id,
+                error: self.last_error.clone(),
+            });
+        }
+
+        self.status.lock().unwrap().state = state.clone();
+        self.state = state;
     }
 
-    pub fn create_new_video_file(&mut self) -> Result<()> {
-        // Release previous writer
-        if let Some(mut writer) = self.writer.take() {
-            writer.release()?;
+    /// Wait before the next reconnection attempt.
+    ///
+    /// Returns `false` when the policy gives up or shutdown was requested.
+    fn wait_before_retry(&mut self, backoff: &mut Backoff) -> bool {
+        match backoff.next_delay() {
+            Some(delay) => {
+                self.set_state(ConnectionState::Retrying {
+                    attempt: backoff.attempts(),
+                    delay,
+                });
+                !self.shutdown.sleep(delay)
+            }
+            None => {
+                self.set_state(ConnectionState::GaveUp {
+                    attempts: backoff.attempts() - 1,
+                });
+                false
+            }
         }
+    }
 
-        // Create camera-specific output directory
-        let camera_dir = PathBuf::from(&self.output_dir).join(format!(
-            "camera_{}",
-            self.url
-                .replace("://", "_")
-                .replace("/", "_")
-                .replace(":", "_")
-        ));
-        fs::create_dir_all(&camera_dir).map_err(|e| {
-            opencv::Error::new(
-                opencv::core::StsError,
-                &format!("Failed to create directory: {}", e),
-            )
-        })?;
+    /// Error returned when the reconnect policy gave up
+    fn gave_up_error(&self) -> opencv::Error {
+        let reason = match &self.last_error {
+            Some(error) => format!(": {}", error),
+            None => String::new(),
+        };
+        opencv::Error::new(
+            opencv::core::StsError,
+            &format!("Gave up reconnecting to {}{}", self.display_url(), reason),
+        )
+    }
+}
 
-        // Create new file name with timestamp
-        let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-        let file_name = camera_dir.join(format!("segment_{}.mp4", timestamp));
-
-        // Get video properties from capture
-        if let Some(capture) = &self.capture {
-            let frame_width = capture.get(videoio::CAP_PROP_FRAME_WIDTH)? as i32;
-            let frame_height = capture.get(videoio::CAP_PROP_FRAME_HEIGHT)? as i32;
-            let stream_fps = capture.get(videoio::CAP_PROP_FPS)?;
-            
-            let fps = if self.use_custom_fps {
-                self.custom_fps
-            } else if stream_fps > 0.0 {
-                stream_fps
-            } else {
-                30.0 // Default fallback
-            };
-
-            // Create new video writer with MP4V codec
-            let fourcc = videoio::VideoWriter::fourcc('m', 'p', '4', 'v')?;
-            let writer = videoio::VideoWriter::new(
-                file_name.to_str().unwrap(),
-                fourcc,
-                fps,
-                (frame_width, frame_height).into(),
-                true,
-            )?;
-
-            if !writer.is_opened()? {
-                return Err(opencv::Error::new(
-                    opencv::core::StsError,
-                    "Failed to create video writer",
-                ));
-            }
+/// Minimum length of a wall-clock aligned segment; shorter ones are merged into the next
+const MIN_ALIGNED_SEGMENT: Duration = Duration::from_secs(1);
+
+/// Time from `now` until the next wall-clock multiple of `segment` counted from local midnight
+pub fn time_to_next_boundary<Tz: TimeZone>(now: DateTime<Tz>, segment: Duration) -> Duration {
+    let segment_ms = segment.as_millis().max(1);
+    let since_midnight_ms = now.num_seconds_from_midnight() as u128 * 1000
+        + (now.nanosecond() % 1_000_000_000) as u128 / 1_000_000;
+    let remaining_ms = segment_ms - since_midnight_ms % segment_ms;
+
+    let remaining = Duration::from_millis(remaining_ms as u64);
+    if remaining < MIN_ALIGNED_SEGMENT {
+        remaining + segment
+    } else {
+        remaining
+    }
+}
 
-            self.writer = Some(writer);
-            self.current_file_start = Instant::now();
-        }
+/// Audio codecs that can be stream-copied into an MP4 segment
+const MP4_AUDIO_CODECS: &[&str] = &["aac", "mp3", "mp2", "ac3", "eac3", "opus", "alac", "flac"];
 
-        Ok(())
+/// Pick the FFmpeg audio codec for an MP4 segment given the source audio codec
+pub fn audio_codec_for_mp4(source_codec: Option<&str>) -> &'static str {
+    match source_codec {
+        Some(codec) if MP4_AUDIO_CODECS.contains(&codec) => "copy",
+        _ => "aac", // PCM, G.711, G.726 and unknown codecs need transcoding
+    }
+}
+
+/// Probe the codec of the first audio stream with ffprobe, using the
+/// protocol's `input_args`
+pub fn probe_audio_codec(url: &str, input_args: &[String]) -> Option<String> {
+    let mut command = Command::new("ffprobe");
+    command.args(["-v", "error"]);
+    command.args(input_args);
+    command.args([
+        "-select_streams",
+        "a:0",
+        "-show_entries",
+        "stream=codec_name",
+        "-of",
+        "csv=p=0",
+        url,
+    ]);
+
+    let output = command.stdin(Stdio::null()).output().ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let codec = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if codec.is_empty() {
+        None
+    } else {
+        Some(codec)
     }
-} 
\ No newline at end of file
+}
@@ -0,0 +1,312 @@
+//! Motion-triggered recording
+//!
+//! Frames are decoded as in the OpenCV path and fed to a MOG2 background
+//! subtractor. Segments are only written while the moving area exceeds the
+//! configured threshold, plus a pre-roll kept in memory and a post-roll after
+//! the last motion. The pre-roll holds decoded frames, so besides its duration
+//! it is capped in bytes.
+
+use opencv::{core, imgproc, prelude::*, video, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Frames are scaled down to this width before background subtraction
+const DETECTION_WIDTH: f64 = 320.0;
+
+/// Frames after (re)connecting during which the background model settles
+const MOTION_WARMUP_FRAMES: u32 = 25;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MotionConfig {
+    /// Fraction of the frame (0.0-1.0) that must change to count as motion
+    #[serde(default = "default_area_threshold")]
+    pub area_threshold: f64,
+    /// Seconds of video kept from before the motion started
+    #[serde(default = "default_pre_roll_secs")]
+    pub pre_roll_secs: u64,
+    /// Memory the pre-roll may use; older frames are dropped beyond it
+    #[serde(default = "default_pre_roll_max_bytes")]
+    pub pre_roll_max_bytes: u64,
+    /// Seconds recording continues after the last motion
+    #[serde(default = "default_post_roll_secs")]
+    pub post_roll_secs: u64,
+    /// Number of frames the background model remembers
+    #[serde(default = "default_history")]
+    pub history: i32,
+    /// MOG2 variance threshold, higher values ignore smaller changes
+    #[serde(default = "default_var_threshold")]
+    pub var_threshold: f64,
+}
+
+fn default_area_threshold() -> f64 {
+    0.01
+}
+
+fn default_pre_roll_secs() -> u64 {
+    5
+}
+
+fn default_pre_roll_max_bytes() -> u64 {
+    256 * 1024 * 1024
+}
+
+fn default_post_roll_secs() -> u64 {
+    10
+}
+
+fn default_history() -> i32 {
+    500
+}
+
+fn default_var_threshold() -> f64 {
+    16.0
+}
+
+impl Default for MotionConfig {
+    fn default() -> Self {
+        Self {
+            area_threshold: default_area_threshold(),
+            pre_roll_secs: default_pre_roll_secs(),
+            pre_roll_max_bytes: default_pre_roll_max_bytes(),
+            post_roll_secs: default_post_roll_secs(),
+            history: default_history(),
+            var_threshold: default_var_threshold(),
+        }
+    }
+}
+
+/// Transition reported by [`MotionGate`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MotionEvent {
+    Started,
+    Stopped,
+}
+
+/// Decides when to record: from the first motion until `post_roll` without motion
+#[derive(Debug, Clone)]
+pub struct MotionGate {
+    post_roll: Duration,
+    recording: bool,
+    last_motion: Option<Instant>,
+}
+
+impl MotionGate {
+    pub fn new(post_roll: Duration) -> Self {
+        Self {
+            post_roll,
+            recording: false,
+            last_motion: None,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Feed the detection result of one frame
+    pub fn update(&mut self, motion: bool, now: Instant) -> Option<MotionEvent> {
+        if motion {
+            self.last_motion = Some(now);
+            if !self.recording {
+                self.recording = true;
+                return Some(MotionEvent::Started);
+            }
+            return None;
+        }
+
+        let quiet_for = self
+            .last_motion
+            .map_or(Duration::MAX, |last| now.saturating_duration_since(last));
+        if self.recording && quiet_for >= self.post_roll {
+            self.recording = false;
+            return Some(MotionEvent::Stopped);
+        }
+        None
+    }
+
+    pub fn reset(&mut self) {
+        self.recording = false;
+        self.last_motion = None;
+    }
+}
+
+/// Items of the last `window`, oldest first, using at most `max_bytes`
+#[derive(Debug)]
+pub struct PreRollBuffer<T> {
+    window: Duration,
+    max_bytes: u64,
+    bytes: u64,
+    items: VecDeque<(Instant, u64, T)>,
+}
+
+impl<T> PreRollBuffer<T> {
+    pub fn new(window: Duration, max_bytes: u64) -> Self {
+        Self {
+            window,
+            max_bytes,
+            bytes: 0,
+            items: VecDeque::new(),
+        }
+    }
+
+    /// Add an item of `size` bytes, dropping the oldest ones that fall out of
+    /// the window or exceed the byte cap
+    pub fn push(&mut self, now: Instant, item: T, size: u64) {
+        self.items.push_back((now, size, item));
+        self.bytes += size;
+        while let Some(&(oldest, size, _)) = self.items.front() {
+            if now.saturating_duration_since(oldest) > self.window || self.bytes > self.max_bytes {
+                self.bytes -= size;
+                self.items.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Bytes of the buffered items
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Remove and return everything buffered
+    pub fn drain(&mut self) -> Vec<T> {
+        self.bytes = 0;
+        self.items.drain(..).map(|(_, _, item)| item).collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes = 0;
+        self.items.clear();
+    }
+}
+
+/// Fraction of moving pixels per frame from a MOG2 background subtractor
+pub struct MotionDetector {
+    subtractor: core::Ptr<video::BackgroundSubtractorMOG2>,
+    kernel: Mat,
+    small: Mat,
+    foreground: Mat,
+    mask: Mat,
+}
+
+impl MotionDetector {
+    pub fn new(config: &MotionConfig) -> Result<Self> {
+        Ok(Self {
+            // Shadows are not motion worth recording
+            subtractor: video::create_background_subtractor_mog2(
+                config.history.max(1),
+                config.var_threshold,
+                false,
+            )?,
+            kernel: imgproc::get_structuring_element(
+                imgproc::MORPH_ELLIPSE,
+                core::Size::new(3, 3),
+                core::Point::new(-1, -1),
+            )?,
+            small: Mat::default(),
+            foreground: Mat::default(),
+            mask: Mat::default(),
+        })
+    }
+
+    pub fn moving_fraction(&mut self, frame: &Mat) -> Result<f64> {
+        let scale = (DETECTION_WIDTH / frame.cols().max(1) as f64).min(1.0);
+        imgproc::resize(
+            frame,
+            &mut self.small,
+            core::Size::new(0, 0),
+            scale,
+            scale,
+            imgproc::INTER_AREA,
+        )?;
+        self.subtractor.apply(&self.small, &mut self.foreground, -1.0)?;
+
+        // Remove single-pixel noise before measuring the moving area
+        imgproc::morphology_ex(
+            &self.foreground,
+            &mut self.mask,
+            imgproc::MORPH_OPEN,
+            &self.kernel,
+            core::Point::new(-1, -1),
+            1,
+            core::BORDER_CONSTANT,
+            imgproc::morphology_default_border_value()?,
+        )?;
+
+        let total = self.mask.total();
+        if total == 0 {
+            return Ok(0.0);
+        }
+        Ok(core::count_non_zero(&self.mask)? as f64 / total as f64)
+    }
+}
+
+/// Detector, gate and pre-roll of one camera
+pub struct MotionState {
+    detector: MotionDetector,
+    gate: MotionGate,
+    pre_roll: PreRollBuffer<Mat>,
+    area_threshold: f64,
+    frames_seen: u32,
+}
+
+impl MotionState {
+    pub fn new(config: &MotionConfig) -> Result<Self> {
+        Ok(Self {
+            detector: MotionDetector::new(config)?,
+            gate: MotionGate::new(Duration::from_secs(config.post_roll_secs)),
+            pre_roll: PreRollBuffer::new(
+                Duration::from_secs(config.pre_roll_secs),
+                config.pre_roll_max_bytes,
+            ),
+            area_threshold: config.area_threshold,
+            frames_seen: 0,
+        })
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.gate.is_recording()
+    }
+
+    /// Run detection on a raw frame; outside a recording `recorded`, the frame
+    /// as it would be written (e.g. with the overlay), goes to the pre-roll
+    pub fn observe(
+        &mut self,
+        frame: &Mat,
+        recorded: &Mat,
+        now: Instant,
+    ) -> Result<Option<MotionEvent>> {
+        let fraction = self.detector.moving_fraction(frame)?;
+        self.frames_seen = self.frames_seen.saturating_add(1);
+        let motion = self.frames_seen > MOTION_WARMUP_FRAMES && fraction >= self.area_threshold;
+
+        let event = self.gate.update(motion, now);
+        if !self.gate.is_recording() {
+            let size = (recorded.total() * recorded.elem_size()?) as u64;
+            self.pre_roll.push(now, recorded.try_clone()?, size);
+        }
+        Ok(event)
+    }
+
+    /// Buffered frames from before the motion, oldest first
+    pub fn take_pre_roll(&mut self) -> Vec<Mat> {
+        self.pre_roll.drain()
+    }
+
+    /// Forget the gate and pre-roll after the stream was reopened
+    pub fn reset(&mut self) {
+        self.gate.reset();
+        self.pre_roll.clear();
+        self.frames_seen = 0;
+    }
+}
@@ -0,0 +1,471 @@
+//! OpenCV recorder backend
+//!
+//! Decodes the stream and re-encodes every frame into MP4 segments. Needed
+//! for custom frame rates, motion-triggered recording and the preview window.
+
+use chrono::{DateTime, Local};
+use opencv::{core::Vector, prelude::*, videoio, Result};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::rtsp::backend::{BackendHealth, BackendStats, RecorderBackend};
+use crate::rtsp::camera::RtspTransport;
+use crate::rtsp::error::CaptureError;
+use crate::rtsp::motion::{MotionEvent, MotionState};
+use crate::rtsp::overlay;
+use crate::rtsp::probe;
+use crate::rtsp::protocol::InputProtocol;
+use crate::rtsp::segments::ClosedSegment;
+use crate::rtsp::snapshot::SnapshotWriter;
+use crate::rtsp::{time_to_next_boundary, OutputFormat, RTSPCapture};
+
+/// Environment variable OpenCV reads FFmpeg demuxer options from
+const CAPTURE_OPTIONS_ENV: &str = "OPENCV_FFMPEG_CAPTURE_OPTIONS";
+
+/// The options are read from the process environment while a capture opens.
+/// Opens that change them hold the write lock; all other opens share the read
+/// lock so they never read the environment while it is being written.
+static CAPTURE_OPTIONS_LOCK: RwLock<()> = RwLock::new(());
+
+pub struct OpenCvBackend {
+    capture: Option<videoio::VideoCapture>,
+    writer: Option<videoio::VideoWriter>,
+    frame: Mat,
+    window: Option<String>,
+    motion: Option<MotionState>,
+    snapshots: Option<SnapshotWriter>,
+    current_file: Option<PathBuf>,
+    current_file_started_at: DateTime<Local>,
+    current_file_start: Instant,
+    current_segment_length: Duration,
+    last_frame_at: Instant,
+    /// Time per frame when reading a file, which OpenCV would otherwise read as fast as it can
+    frame_interval: Option<Duration>,
+    stats: BackendStats,
+}
+
+impl Default for OpenCvBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenCvBackend {
+    pub fn new() -> Self {
+        Self {
+            capture: None,
+            writer: None,
+            frame: Mat::default(),
+            window: None,
+            motion: None,
+            snapshots: None,
+            current_file: None,
+            current_file_started_at: Local::now(),
+            current_file_start: Instant::now(),
+            current_segment_length: Duration::ZERO,
+            last_frame_at: Instant::now(),
+            frame_interval: None,
+            stats: BackendStats::default(),
+        }
+    }
+
+    /// Open the stream. The timeouts make blocked opens and reads on a dead
+    /// stream return so the capture can reconnect.
+    fn open_capture(&mut self, capture: &RTSPCapture) -> Result<()> {
+        let timeout_ms = capture.stall_timeout.as_millis().min(i32::MAX as u128) as i32;
+        let params = Vector::<i32>::from_slice(&[
+            videoio::CAP_PROP_OPEN_TIMEOUT_MSEC,
+            timeout_ms,
+            videoio::CAP_PROP_READ_TIMEOUT_MSEC,
+            timeout_ms,
+        ]);
+        // FFMPEG backend for better control. OpenCV already prefers TCP for
+        // RTSP, so the environment is only changed for the other transports.
+        let options = match capture.protocol {
+            InputProtocol::Rtsp
+                if capture.transport != RtspTransport::Tcp
+                    || env::var_os(CAPTURE_OPTIONS_ENV).is_some() =>
+            {
+                vec![("rtsp_transport", capture.transport.as_ffmpeg_str())]
+            }
+            _ => Vec::new(),
+        };
+        let mut stream = open_with_ffmpeg_options(&capture.input_url(), &params, &options)?;
+
+        if !stream.is_opened()? {
+            return Err(opencv::Error::new(
+                opencv::core::StsError,
+                "Failed to open stream",
+            ));
+        }
+
+        // Get the stream's FPS
+        let stream_fps = stream.get(videoio::CAP_PROP_FPS)?;
+        let actual_fps = if stream_fps <= 0.0 {
+            if capture.use_custom_fps {
+                capture.custom_fps
+            } else {
+                probe::FALLBACK_FPS // Default fallback
+            }
+        } else {
+            stream_fps
+        };
+
+        println!("Stream FPS: {}", actual_fps);
+        self.frame_interval =
+            (!capture.protocol.is_live()).then(|| Duration::from_secs_f64(1.0 / actual_fps));
+
+        // Optimize for video-only capture
+        let _ = stream.set(videoio::CAP_PROP_CONVERT_RGB, 1.0);
+
+        self.capture = Some(stream);
+        Ok(())
+    }
+
+    /// Release the OpenCV writer and report its finished segment
+    fn close_video_file(&mut self, capture: &mut RTSPCapture) -> Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.release()?;
+        }
+        if let Some(path) = self.current_file.take() {
+            self.stats.segments_closed += 1;
+            capture.on_segment_closed(ClosedSegment {
+                path,
+                start: Some(self.current_file_started_at),
+                end: Some(Local::now()),
+            });
+        }
+        Ok(())
+    }
+
+    fn create_new_video_file(&mut self, capture: &mut RTSPCapture) -> Result<()> {
+        // Release previous writer
+        self.close_video_file(capture)?;
+
+        // Create camera-specific output directory
+        let camera_dir = capture.camera_dir();
+        fs::create_dir_all(&camera_dir).map_err(|e| {
+            opencv::Error::new(
+                opencv::core::StsError,
+                &format!("Failed to create directory: {}", e),
+            )
+        })?;
+
+        // Create new file name with timestamp
+        let timestamp = Local::now().format("%Y%m%d_%H%M%S");
+        let file_name = camera_dir.join(format!("segment_{}.mp4", timestamp));
+
+        // Get video properties from capture
+        if let Some(stream) = &self.capture {
+            let frame_width = stream.get(videoio::CAP_PROP_FRAME_WIDTH)? as i32;
+            let frame_height = stream.get(videoio::CAP_PROP_FRAME_HEIGHT)? as i32;
+            let stream_fps = stream.get(videoio::CAP_PROP_FPS)?;
+
+            let fps = if capture.use_custom_fps {
+                capture.custom_fps
+            } else if stream_fps > 0.0 {
+                stream_fps
+            } else {
+                probe::FALLBACK_FPS // Default fallback
+            };
+
+            // Create new video writer with MP4V codec
+            let fourcc = videoio::VideoWriter::fourcc('m', 'p', '4', 'v')?;
+            let writer = videoio::VideoWriter::new(
+                file_name.to_str().unwrap(),
+                fourcc,
+                fps,
+                (frame_width, frame_height).into(),
+                true,
+            )?;
+
+            if !writer.is_opened()? {
+                return Err(opencv::Error::new(
+                    opencv::core::StsError,
+                    "Failed to create video writer",
+                ));
+            }
+
+            self.writer = Some(writer);
+            capture.on_segment_started(&file_name);
+            self.current_file = Some(file_name);
+            self.current_file_started_at = Local::now();
+            self.current_file_start = Instant::now();
+            self.current_segment_length = if capture.align_segments {
+                time_to_next_boundary(Local::now(), capture.segment_duration)
+            } else {
+                capture.segment_duration
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Motion detection, recording, snapshots and preview for one decoded frame
+    fn handle_frame(&mut self, capture: &mut RTSPCapture) -> Result<()> {
+        let frame = std::mem::take(&mut self.frame);
+
+        // The overlay goes on a copy: motion detection must not see the
+        // changing timestamp, while pre-roll, snapshots and preview carry it
+        let overlaid = match &capture.overlay {
+            Some(config) => {
+                let mut overlaid = frame.try_clone()?;
+                overlay::draw_overlay(&mut overlaid, config, &capture.camera_name, Local::now())?;
+                Some(overlaid)
+            }
+            None => None,
+        };
+        let recorded = overlaid.as_ref().unwrap_or(&frame);
+
+        let recording = match self
+            .motion
+            .as_mut()
+            .map(|motion| motion.observe(&frame, recorded, Instant::now()))
+        {
+            Some(event) => match event? {
+                Some(MotionEvent::Started) => {
+                    println!("Motion detected on {}, recording", capture.camera_name);
+                    self.create_new_video_file(capture)?;
+                    let pre_roll = self
+                        .motion
+                        .as_mut()
+                        .map(|motion| motion.take_pre_roll())
+                        .unwrap_or_default();
+                    if let Some(writer) = &mut self.writer {
+                        for buffered in pre_roll {
+                            writer.write(&buffered)?;
+                        }
+                    }
+                    true
+                }
+                Some(MotionEvent::Stopped) => {
+                    println!("Motion ended on {}, pausing recording", capture.camera_name);
+                    self.close_video_file(capture)?;
+                    false
+                }
+                None => self.motion.as_ref().is_some_and(|motion| motion.is_recording()),
+            },
+            None => true,
+        };
+
+        // Write frame to file
+        if recording {
+            if let Some(writer) = &mut self.writer {
+                writer.write(recorded)?;
+                self.stats.frames_written += 1;
+            }
+        }
+
+        if let Some(snapshots) = &mut self.snapshots {
+            if let Err(e) = snapshots.maybe_write(&capture.camera_dir(), recorded) {
+                eprintln!("Failed to write snapshot for {}: {}", capture.camera_name, e.message);
+            }
+        }
+
+        // Show preview window
+        if let Some(window_name) = &self.window {
+            opencv::highgui::imshow(window_name, recorded)?;
+        }
+
+        self.frame = frame;
+        Ok(())
+    }
+}
+
+impl RecorderBackend for OpenCvBackend {
+    fn name(&self) -> &str {
+        "opencv"
+    }
+
+    fn start(&mut self, capture: &mut RTSPCapture) -> Result<()> {
+        if capture.show_preview && self.window.is_none() {
+            let window_name = format!("RTSP Stream - {}", capture.display_url());
+            opencv::highgui::named_window(&window_name, opencv::highgui::WINDOW_AUTOSIZE)?;
+            self.window = Some(window_name);
+        }
+        if self.snapshots.is_none() {
+            self.snapshots = capture.snapshots.clone().map(SnapshotWriter::new);
+        }
+        // A reconnect starts over with an empty pre-roll
+        if let Some(motion) = &mut self.motion {
+            motion.reset();
+        } else if let Some(config) = &capture.motion {
+            self.motion = Some(MotionState::new(config)?);
+        }
+
+        if capture.output_format == OutputFormat::Hls {
+            eprintln!(
+                "Warning: HLS output is not supported in OpenCV mode, {} will be recorded as MP4 segments",
+                capture.display_url()
+            );
+        }
+
+        if capture.audio {
+            eprintln!(
+                "Warning: audio recording is not supported in OpenCV mode, audio for {} will be dropped",
+                capture.display_url()
+            );
+        }
+
+        self.open_capture(capture)?;
+        self.last_frame_at = Instant::now();
+
+        if self.snapshots.is_some() {
+            fs::create_dir_all(capture.camera_dir()).map_err(|e| {
+                opencv::Error::new(
+                    opencv::core::StsError,
+                    &format!("Failed to create directory: {}", e),
+                )
+            })?;
+        }
+
+        // Motion mode opens a file once something moves
+        if self.motion.is_none() {
+            self.create_new_video_file(capture)?;
+        }
+        Ok(())
+    }
+
+    fn poll(&mut self, capture: &mut RTSPCapture) -> Result<BackendHealth> {
+        // Check if we need to start a new segment (motion mode only while recording)
+        let idle = self.motion.as_ref().is_some_and(|motion| !motion.is_recording());
+        if !idle && self.current_file_start.elapsed() >= self.current_segment_length {
+            self.rotate(capture)?;
+            return Ok(BackendHealth::Running);
+        }
+
+        // Read new frame if available
+        let read_result = match self.capture.as_mut() {
+            Some(stream) => stream.read(&mut self.frame),
+            None => Ok(false),
+        };
+        let frame_read = match read_result {
+            Ok(frame_read) => frame_read,
+            Err(e) => {
+                eprintln!("Error reading from {}: {}", capture.display_url(), e.message);
+                false
+            }
+        };
+
+        if frame_read && !self.frame.empty() {
+            self.last_frame_at = Instant::now();
+            self.stats.last_frame_at = Some(self.last_frame_at);
+            self.handle_frame(capture)?;
+        } else if !frame_read
+            && !capture.protocol.is_live()
+            && self.last_frame_at.elapsed() < capture.stall_timeout
+        {
+            // End of a looped file: start over without reconnecting
+            if let Some(stream) = &mut self.capture {
+                stream.set(videoio::CAP_PROP_POS_FRAMES, 0.0)?;
+            }
+        } else if !frame_read || self.last_frame_at.elapsed() >= capture.stall_timeout {
+            // Stream ended, failed or stalled: the capture finalizes the segment and reconnects
+            eprintln!(
+                "Stream {} {}, reconnecting...",
+                capture.display_url(),
+                if frame_read { "stalled" } else { "lost" }
+            );
+            return Ok(BackendHealth::Failed(CaptureError::Timeout(format!(
+                "no frame for {:.1}s",
+                self.last_frame_at.elapsed().as_secs_f64()
+            ))));
+        }
+
+        // Wait for a short duration
+        if self.window.is_some() {
+            let key = opencv::highgui::wait_key(1)?;
+            if key == 27 {
+                // ESC key
+                return Ok(BackendHealth::Finished);
+            }
+        } else if let Some(interval) = self.frame_interval {
+            thread::sleep(interval.saturating_sub(self.last_frame_at.elapsed()));
+        } else {
+            thread::sleep(Duration::from_millis(10)); // Adjust as needed
+        }
+        Ok(BackendHealth::Running)
+    }
+
+    fn rotate(&mut self, capture: &mut RTSPCapture) -> Result<()> {
+        self.create_new_video_file(capture)
+    }
+
+    /// Release the writer (finalizing its segment) and the stream
+    fn stop(&mut self, capture: &mut RTSPCapture) -> Result<()> {
+        self.close_video_file(capture)?;
+        if let Some(mut stream) = self.capture.take() {
+            stream.release()?;
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> BackendStats {
+        BackendStats {
+            current_segment: self.current_file.clone(),
+            ..self.stats.clone()
+        }
+    }
+}
+
+impl Drop for OpenCvBackend {
+    fn drop(&mut self) {
+        if let Some(window_name) = &self.window {
+            let _ = opencv::highgui::destroy_window(window_name);
+        }
+    }
+}
+
+/// Open a capture through FFmpeg with demuxer options (e.g. `rtsp_transport`)
+/// passed in `OPENCV_FFMPEG_CAPTURE_OPTIONS`. Options set in the environment
+/// outside the recorder are kept unless overridden, and restored afterwards.
+///
+/// OpenCV has no per-capture way to pass demuxer options, so opens with
+/// options run one at a time; opens without options run concurrently.
+pub fn open_with_ffmpeg_options(
+    url: &str,
+    params: &Vector<i32>,
+    options: &[(&str, &str)],
+) -> Result<videoio::VideoCapture> {
+    if options.is_empty() {
+        let _guard = CAPTURE_OPTIONS_LOCK.read().unwrap_or_else(|e| e.into_inner());
+        return videoio::VideoCapture::from_file_with_params(url, videoio::CAP_FFMPEG, params);
+    }
+
+    let _guard = CAPTURE_OPTIONS_LOCK.write().unwrap_or_else(|e| e.into_inner());
+    let previous = env::var_os(CAPTURE_OPTIONS_ENV);
+    env::set_var(
+        CAPTURE_OPTIONS_ENV,
+        merge_capture_options(previous.as_ref().and_then(|v| v.to_str()), options),
+    );
+
+    let result = videoio::VideoCapture::from_file_with_params(url, videoio::CAP_FFMPEG, params);
+
+    match previous {
+        Some(previous) => env::set_var(CAPTURE_OPTIONS_ENV, previous),
+        None => env::remove_var(CAPTURE_OPTIONS_ENV),
+    }
+    result
+}
+
+/// Build an `OPENCV_FFMPEG_CAPTURE_OPTIONS` value (`key;value|key;value`)
+/// from existing options and overrides
+pub fn merge_capture_options(existing: Option<&str>, options: &[(&str, &str)]) -> String {
+    let kept = existing
+        .unwrap_or_default()
+        .split('|')
+        .filter(|entry| !entry.is_empty())
+        .filter(|entry| {
+            let key = entry.split(';').next().unwrap_or_default();
+            !options.iter().any(|(option, _)| *option == key)
+        })
+        .map(str::to_string);
+    let entries: Vec<String> = kept
+        .chain(options.iter().map(|(key, value)| format!("{};{}", key, value)))
+        .collect();
+    entries.join("|")
+}
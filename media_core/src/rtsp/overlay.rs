@@ -0,0 +1,264 @@
+//! Burned-in text overlay
+//!
+//! Draws the wall-clock time, the camera name and an optional custom text
+//! onto recorded frames. The OpenCV path draws on every decoded frame; the
+//! FFmpeg path adds a `drawtext` filter, which means re-encoding instead of
+//! stream-copying.
+
+use chrono::{DateTime, Local};
+use opencv::{core, imgproc, prelude::*, Result};
+use serde::{Deserialize, Serialize};
+
+/// Distance of the text block from the frame edges in pixels
+const MARGIN: i32 = 10;
+
+/// Corner of the frame the text is drawn in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayPosition {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OverlayConfig {
+    /// Draw the wall-clock time of each frame
+    #[serde(default = "default_true")]
+    pub timestamp: bool,
+    /// strftime format of the timestamp
+    #[serde(default = "default_timestamp_format")]
+    pub timestamp_format: String,
+    #[serde(default = "default_true")]
+    pub camera_name: bool,
+    /// Extra line below the camera name
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub position: OverlayPosition,
+    /// OpenCV font scale; FFmpeg uses a font size of 30 pixels per unit
+    #[serde(default = "default_font_scale")]
+    pub font_scale: f64,
+    /// Draw the text on a black box for legibility
+    #[serde(default = "default_true")]
+    pub background: bool,
+    /// Let the FFmpeg backend re-encode (libx264) to burn the overlay in.
+    /// Without it the FFmpeg backend keeps stream-copying and skips the overlay.
+    #[serde(default)]
+    pub reencode: bool,
+    /// Font for FFmpeg's `drawtext`; the fontconfig default when unset
+    #[serde(default)]
+    pub font_file: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_timestamp_format() -> String {
+    "%Y-%m-%d %H:%M:%S".to_string()
+}
+
+fn default_font_scale() -> f64 {
+    0.7
+}
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        Self {
+            timestamp: true,
+            timestamp_format: default_timestamp_format(),
+            camera_name: true,
+            text: None,
+            position: OverlayPosition::default(),
+            font_scale: default_font_scale(),
+            background: true,
+            reencode: false,
+            font_file: None,
+        }
+    }
+}
+
+impl OverlayConfig {
+    /// Lines drawn on a frame taken at `now`, top to bottom
+    pub fn lines(&self, camera_name: &str, now: DateTime<Local>) -> Vec<String> {
+        let mut lines = Vec::new();
+        if self.timestamp {
+            lines.push(now.format(&self.timestamp_format).to_string());
+        }
+        if self.camera_name {
+            lines.push(camera_name.to_string());
+        }
+        if let Some(text) = self.text.as_ref().filter(|text| !text.is_empty()) {
+            lines.push(text.clone());
+        }
+        lines
+    }
+}
+
+/// Draw the overlay onto a decoded frame
+pub fn draw_overlay(
+    frame: &mut Mat,
+    config: &OverlayConfig,
+    camera_name: &str,
+    now: DateTime<Local>,
+) -> Result<()> {
+    let lines = config.lines(camera_name, now);
+    if lines.is_empty() || frame.empty() {
+        return Ok(());
+    }
+
+    let font = imgproc::FONT_HERSHEY_SIMPLEX;
+    let scale = config.font_scale.max(0.1);
+    let thickness = ((scale * 2.0).round() as i32).max(1);
+    let padding = ((scale * 6.0).round() as i32).max(2);
+
+    let mut text_height = 0;
+    let mut block_width = 0;
+    for line in &lines {
+        let mut baseline = 0;
+        let size = imgproc::get_text_size(line, font, scale, thickness, &mut baseline)?;
+        text_height = text_height.max(size.height + baseline);
+        block_width = block_width.max(size.width);
+    }
+    let line_height = text_height + padding;
+    let block_height = line_height * lines.len() as i32 - padding;
+
+    let frame_size = frame.size()?;
+    let left = MARGIN + padding;
+    let right = frame_size.width - block_width - MARGIN - padding;
+    let top = MARGIN + padding;
+    let bottom = frame_size.height - block_height - MARGIN - padding;
+    let (x, y) = match config.position {
+        OverlayPosition::TopLeft => (left, top),
+        OverlayPosition::TopRight => (right, top),
+        OverlayPosition::BottomLeft => (left, bottom),
+        OverlayPosition::BottomRight => (right, bottom),
+    };
+
+    if config.background {
+        imgproc::rectangle(
+            frame,
+            core::Rect::new(
+                x - padding,
+                y - padding,
+                block_width + 2 * padding,
+                block_height + 2 * padding,
+            ),
+            core::Scalar::new(0.0, 0.0, 0.0, 0.0),
+            imgproc::FILLED,
+            imgproc::LINE_8,
+            0,
+        )?;
+    }
+
+    for (index, line) in lines.iter().enumerate() {
+        // put_text places the baseline at the origin
+        let mut baseline = 0;
+        let size = imgproc::get_text_size(line, font, scale, thickness, &mut baseline)?;
+        let origin = core::Point::new(x, y + line_height * index as i32 + size.height);
+        imgproc::put_text(
+            frame,
+            line,
+            origin,
+            font,
+            scale,
+            core::Scalar::new(255.0, 255.0, 255.0, 0.0),
+            thickness,
+            imgproc::LINE_AA,
+            false,
+        )?;
+    }
+    Ok(())
+}
+
+/// FFmpeg video arguments that burn the overlay in with `drawtext`, replacing `-c:v copy`
+pub fn ffmpeg_video_args(config: &OverlayConfig, camera_name: &str) -> Vec<String> {
+    let filter = drawtext_filter(config, camera_name);
+    [
+        "-vf",
+        filter.as_str(),
+        "-c:v",
+        "libx264",
+        "-preset",
+        "veryfast",
+        // Keyframes every 2 seconds so segments can still be cut on time
+        "-force_key_frames",
+        "expr:gte(t,n_forced*2)",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect()
+}
+
+/// `drawtext` filter showing the same lines as the OpenCV overlay
+pub fn drawtext_filter(config: &OverlayConfig, camera_name: &str) -> String {
+    // Text as expanded by drawtext: the time is a %{localtime} function, the
+    // rest is literal
+    let mut lines = Vec::new();
+    if config.timestamp {
+        lines.push(format!(
+            "%{{localtime:{}}}",
+            escape(&config.timestamp_format, &['\\', ':', '}', '\''])
+        ));
+    }
+    if config.camera_name {
+        lines.push(escape(camera_name, &['\\', '%']));
+    }
+    if let Some(text) = config.text.as_ref().filter(|text| !text.is_empty()) {
+        lines.push(escape(text, &['\\', '%']));
+    }
+
+    let margin = MARGIN.to_string();
+    let (x, y) = match config.position {
+        OverlayPosition::TopLeft => (margin.clone(), margin),
+        OverlayPosition::TopRight => (format!("w-tw-{}", margin), margin),
+        OverlayPosition::BottomLeft => (margin.clone(), format!("h-th-{}", margin)),
+        OverlayPosition::BottomRight => (format!("w-tw-{}", margin), format!("h-th-{}", margin)),
+    };
+    let font_size = (config.font_scale * 30.0).round().max(1.0);
+
+    let mut options = vec![
+        format!("text={}", escape_option(&lines.join("\n"))),
+        format!("x={}", x),
+        format!("y={}", y),
+        format!("fontsize={}", font_size),
+        "fontcolor=white".to_string(),
+    ];
+    if config.background {
+        options.push("box=1".to_string());
+        options.push("boxcolor=black".to_string());
+        options.push(format!(
+            "boxborderw={}",
+            ((config.font_scale * 6.0).round() as i32).max(2)
+        ));
+    }
+    if let Some(font_file) = &config.font_file {
+        options.push(format!("fontfile={}", escape_option(font_file)));
+    }
+
+    // Filtergraph level: the options string is a single filter argument
+    format!(
+        "drawtext={}",
+        escape(&options.join(":"), &['\\', '\'', '[', ']', ',', ';'])
+    )
+}
+
+/// Escape an option value of a filter (`key=value:key=value`)
+fn escape_option(value: &str) -> String {
+    escape(value, &['\\', '\'', ':'])
+}
+
+/// Prefix every occurrence of `special` with a backslash
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
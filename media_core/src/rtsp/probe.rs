@@ -0,0 +1,240 @@
+//! Stream diagnostics for the `probe` mode
+//!
+//! Combines what ffprobe reports about a source with what OpenCV sees when
+//! opening it the way the capture code does, so a wrong-looking recording can
+//! be traced back to the stream (codec, resolution, missing fps, ...).
+
+use opencv::{core::Vector, prelude::*, videoio};
+use serde::Serialize;
+use std::fmt;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::rtsp::camera::RtspTransport;
+use crate::rtsp::credentials::{redact_text, redact_url};
+use crate::rtsp::protocol::InputProtocol;
+
+/// Frame rate the recorders fall back on when the stream reports none
+pub const FALLBACK_FPS: f64 = 30.0;
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct AudioTrack {
+    pub index: u32,
+    pub codec: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u32>,
+}
+
+/// What was found out about a source
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct StreamReport {
+    /// Source without credentials
+    pub source: String,
+    /// Protocol detected from the source
+    pub protocol: InputProtocol,
+    pub container: Option<String>,
+    pub video_codec: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Average frame rate reported by ffprobe
+    pub fps: Option<f64>,
+    /// `CAP_PROP_FPS` as reported by OpenCV
+    pub opencv_fps: Option<f64>,
+    /// Frame rate the recorders use; falls back to 30 when OpenCV reports none
+    pub effective_fps: Option<f64>,
+    pub fps_fallback: bool,
+    pub bitrate_bps: Option<u64>,
+    pub audio_tracks: Vec<AudioTrack>,
+    /// Time from opening the source to the first decoded frame
+    pub time_to_first_frame_ms: Option<u64>,
+    pub errors: Vec<String>,
+}
+
+/// Probe a URL or file with ffprobe and OpenCV
+pub fn probe_source(source: &str, timeout: Duration) -> StreamReport {
+    let mut report = StreamReport {
+        source: redact_url(source),
+        protocol: InputProtocol::detect(source),
+        ..StreamReport::default()
+    };
+
+    match run_ffprobe(source) {
+        Ok(json) => {
+            if let Err(e) = apply_ffprobe_json(&mut report, &json) {
+                report.errors.push(format!("ffprobe: {}", e));
+            }
+        }
+        Err(e) => report.errors.push(format!("ffprobe: {}", e)),
+    }
+
+    if let Err(e) = probe_opencv(&mut report, source, timeout) {
+        report.errors.push(format!("OpenCV: {}", e));
+    }
+    report
+}
+
+fn run_ffprobe(source: &str) -> Result<String, String> {
+    let mut command = Command::new("ffprobe");
+    command.args(["-v", "error", "-show_streams", "-show_format", "-of", "json"]);
+    command.args(InputProtocol::detect(source).probe_args(RtspTransport::default()));
+    let output = command
+        .arg(source)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("failed to run ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        return Err(redact_text(String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Fill the report from `ffprobe -show_streams -show_format -of json` output
+pub fn apply_ffprobe_json(report: &mut StreamReport, json: &str) -> Result<(), String> {
+    let json: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let as_u32 = |value: &serde_json::Value| value.as_u64().map(|v| v as u32);
+    let parse_u64 = |value: &serde_json::Value| value.as_str().and_then(|v| v.parse::<u64>().ok());
+
+    let streams = json["streams"].as_array().cloned().unwrap_or_default();
+    if let Some(video) = streams.iter().find(|s| s["codec_type"] == "video") {
+        report.video_codec = video["codec_name"].as_str().map(str::to_string);
+        report.width = as_u32(&video["width"]);
+        report.height = as_u32(&video["height"]);
+        report.fps = video["avg_frame_rate"]
+            .as_str()
+            .and_then(parse_frame_rate)
+            .or_else(|| video["r_frame_rate"].as_str().and_then(parse_frame_rate));
+        report.bitrate_bps = parse_u64(&video["bit_rate"]);
+    }
+
+    report.audio_tracks = streams
+        .iter()
+        .filter(|s| s["codec_type"] == "audio")
+        .map(|audio| AudioTrack {
+            index: audio["index"].as_u64().unwrap_or_default() as u32,
+            codec: audio["codec_name"].as_str().map(str::to_string),
+            sample_rate: audio["sample_rate"]
+                .as_str()
+                .and_then(|rate| rate.parse().ok()),
+            channels: as_u32(&audio["channels"]),
+        })
+        .collect();
+
+    report.container = json["format"]["format_name"].as_str().map(str::to_string);
+    if report.bitrate_bps.is_none() {
+        report.bitrate_bps = parse_u64(&json["format"]["bit_rate"]);
+    }
+    Ok(())
+}
+
+/// Parse an ffprobe rate such as `30000/1001`; `0/0` means unknown
+pub fn parse_frame_rate(rate: &str) -> Option<f64> {
+    let (num, den) = rate.split_once('/').unwrap_or((rate, "1"));
+    let num: f64 = num.parse().ok()?;
+    let den: f64 = den.parse().ok()?;
+    if num <= 0.0 || den <= 0.0 {
+        return None;
+    }
+    Some(num / den)
+}
+
+/// Open the source like the OpenCV recorder does and time the first frame
+fn probe_opencv(report: &mut StreamReport, source: &str, timeout: Duration) -> opencv::Result<()> {
+    let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+    let params = Vector::from_slice(&[
+        videoio::CAP_PROP_OPEN_TIMEOUT_MSEC,
+        timeout_ms,
+        videoio::CAP_PROP_READ_TIMEOUT_MSEC,
+        timeout_ms,
+    ]);
+
+    let started = Instant::now();
+    let mut capture = videoio::VideoCapture::from_file_with_params(source, videoio::CAP_FFMPEG, &params)?;
+    if !capture.is_opened()? {
+        return Err(opencv::Error::new(
+            opencv::core::StsError,
+            "failed to open source",
+        ));
+    }
+
+    let fps = capture.get(videoio::CAP_PROP_FPS)?;
+    report.opencv_fps = Some(fps);
+    report.fps_fallback = fps <= 0.0;
+    report.effective_fps = Some(if fps > 0.0 { fps } else { FALLBACK_FPS });
+
+    let mut frame = Mat::default();
+    if capture.read(&mut frame)? && !frame.empty() {
+        report.time_to_first_frame_ms = Some(started.elapsed().as_millis() as u64);
+        report.width = report.width.or(Some(frame.cols() as u32));
+        report.height = report.height.or(Some(frame.rows() as u32));
+    } else {
+        report.errors.push("OpenCV: no frame received".to_string());
+    }
+
+    capture.release()?;
+    Ok(())
+}
+
+impl fmt::Display for StreamReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unknown = || "unknown".to_string();
+        writeln!(f, "Source:          {}", self.source)?;
+        writeln!(f, "Protocol:        {}", self.protocol)?;
+        writeln!(f, "Container:       {}", self.container.clone().unwrap_or_else(unknown))?;
+        writeln!(f, "Video codec:     {}", self.video_codec.clone().unwrap_or_else(unknown))?;
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => writeln!(f, "Resolution:      {}x{}", width, height)?,
+            _ => writeln!(f, "Resolution:      unknown")?,
+        }
+        writeln!(
+            f,
+            "FPS (ffprobe):   {}",
+            self.fps.map_or_else(unknown, |fps| format!("{:.2}", fps))
+        )?;
+        writeln!(
+            f,
+            "FPS (OpenCV):    {}",
+            self.opencv_fps.map_or_else(unknown, |fps| format!("{:.2}", fps))
+        )?;
+        if let Some(fps) = self.effective_fps {
+            if self.fps_fallback {
+                writeln!(
+                    f,
+                    "Recording FPS:   {:.2} (fallback, the stream reports no frame rate)",
+                    fps
+                )?;
+            } else {
+                writeln!(f, "Recording FPS:   {:.2}", fps)?;
+            }
+        }
+        writeln!(
+            f,
+            "Bitrate:         {}",
+            self.bitrate_bps
+                .map_or_else(unknown, |bps| format!("{:.1} kbit/s", bps as f64 / 1000.0))
+        )?;
+        if self.audio_tracks.is_empty() {
+            writeln!(f, "Audio tracks:    none")?;
+        }
+        for track in &self.audio_tracks {
+            writeln!(
+                f,
+                "Audio track {}:   {}, {} Hz, {} channel(s)",
+                track.index,
+                track.codec.clone().unwrap_or_else(unknown),
+                track.sample_rate.map_or_else(unknown, |rate| rate.to_string()),
+                track.channels.map_or_else(unknown, |channels| channels.to_string())
+            )?;
+        }
+        writeln!(
+            f,
+            "First frame:     {}",
+            self.time_to_first_frame_ms
+                .map_or_else(unknown, |ms| format!("{} ms", ms))
+        )?;
+        for error in &self.errors {
+            writeln!(f, "Error:           {}", error)?;
+        }
+        Ok(())
+    }
+}
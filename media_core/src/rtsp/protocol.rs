@@ -0,0 +1,123 @@
+//! Input protocols
+//!
+//! Cameras are not all RTSP. The protocol is detected from the URL (or set
+//! per camera) and decides which FFmpeg input options are used to pull the
+//! stream. Everything after the input (segmentation, naming, retention) is
+//! the same for every protocol.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::rtsp::camera::RtspTransport;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum InputProtocol {
+    #[default]
+    Rtsp,
+    Rtmp,
+    /// SRT in caller mode; options such as `latency` go in the URL query
+    Srt,
+    /// FLV over HTTP(S), as served by most streaming servers
+    HttpFlv,
+    /// Motion JPEG as a `multipart/x-mixed-replace` HTTP stream
+    Mjpeg,
+    /// Any other HTTP(S) source (HLS, progressive MP4, ...), left to FFmpeg to detect
+    Http,
+    /// Local file, looped in real time (useful for testing)
+    File,
+    /// Unrecognized scheme, passed to FFmpeg without extra options
+    Other,
+}
+
+impl InputProtocol {
+    /// Detect the protocol from the scheme and, for HTTP, the path of a URL
+    pub fn detect(url: &str) -> Self {
+        let lower = url.trim().to_ascii_lowercase();
+        let Some((scheme, rest)) = lower.split_once("://") else {
+            // No scheme: a plain (possibly Windows) path
+            return InputProtocol::File;
+        };
+
+        match scheme {
+            "rtsp" | "rtsps" => InputProtocol::Rtsp,
+            "rtmp" | "rtmps" | "rtmpt" | "rtmpe" => InputProtocol::Rtmp,
+            "srt" => InputProtocol::Srt,
+            "file" => InputProtocol::File,
+            "http" | "https" => {
+                let path = rest.split(['?', '#']).next().unwrap_or_default();
+                if path.ends_with(".flv") {
+                    InputProtocol::HttpFlv
+                } else if ["mjpg", "mjpeg", "action=stream"]
+                    .iter()
+                    .any(|hint| rest.contains(hint))
+                {
+                    InputProtocol::Mjpeg
+                } else {
+                    InputProtocol::Http
+                }
+            }
+            _ => InputProtocol::Other,
+        }
+    }
+
+    /// Whether the source is a live stream rather than a file
+    pub fn is_live(&self) -> bool {
+        *self != InputProtocol::File
+    }
+
+    /// FFmpeg options placed before `-i` when probing the source
+    pub fn probe_args(&self, transport: RtspTransport) -> Vec<String> {
+        let args = match self {
+            InputProtocol::Rtsp => vec!["-rtsp_transport", transport.as_ffmpeg_str()],
+            InputProtocol::Rtmp => vec!["-rtmp_live", "live"],
+            InputProtocol::HttpFlv => vec!["-f", "flv"],
+            InputProtocol::Mjpeg => vec!["-f", "mpjpeg"],
+            InputProtocol::Srt | InputProtocol::Http | InputProtocol::File | InputProtocol::Other => vec![],
+        };
+        args.into_iter().map(String::from).collect()
+    }
+
+    /// FFmpeg options placed before `-i` when recording the source
+    pub fn input_args(&self, transport: RtspTransport) -> Vec<String> {
+        let mut args = self.probe_args(transport);
+        match self {
+            InputProtocol::File => {
+                // Read at the native rate, endlessly, like a camera
+                args.extend(["-re", "-stream_loop", "-1"].map(String::from));
+            }
+            InputProtocol::HttpFlv | InputProtocol::Mjpeg | InputProtocol::Http => {
+                // Let FFmpeg's HTTP client reconnect when the stream ends or fails
+                args.extend(
+                    [
+                        "-reconnect_at_eof",
+                        "1",
+                        "-reconnect_streamed",
+                        "1",
+                        "-reconnect_delay_max",
+                        "120",
+                    ]
+                    .map(String::from),
+                );
+            }
+            _ => {}
+        }
+        args
+    }
+}
+
+impl fmt::Display for InputProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InputProtocol::Rtsp => "RTSP",
+            InputProtocol::Rtmp => "RTMP",
+            InputProtocol::Srt => "SRT",
+            InputProtocol::HttpFlv => "HTTP-FLV",
+            InputProtocol::Mjpeg => "MJPEG over HTTP",
+            InputProtocol::Http => "HTTP",
+            InputProtocol::File => "file",
+            InputProtocol::Other => "other",
+        };
+        write!(f, "{}", name)
+    }
+}
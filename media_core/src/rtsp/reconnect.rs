@@ -0,0 +1,132 @@
+//! Reconnection policy shared by the FFmpeg and OpenCV capture paths
+//!
+//! Delays grow exponentially from `initial_delay_ms` up to `max_delay_ms` and
+//! are randomized by `jitter` so cameras behind the same switch do not retry
+//! in lockstep.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Delay before the first retry
+    #[serde(default = "default_initial_delay_ms")]
+    pub initial_delay_ms: u64,
+    /// Factor applied to the delay after every failed attempt
+    #[serde(default = "default_multiplier")]
+    pub multiplier: f64,
+    /// Upper bound for the delay
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64,
+    /// Random spread as a fraction of the delay (0.2 means +/-20%)
+    #[serde(default = "default_jitter")]
+    pub jitter: f64,
+    /// Stop retrying after this many consecutive failures (never by default)
+    #[serde(default)]
+    pub give_up_after: Option<u32>,
+}
+
+fn default_initial_delay_ms() -> u64 {
+    1000
+}
+
+fn default_multiplier() -> f64 {
+    2.0
+}
+
+fn default_max_delay_ms() -> u64 {
+    60_000
+}
+
+fn default_jitter() -> f64 {
+    0.2
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay_ms: default_initial_delay_ms(),
+            multiplier: default_multiplier(),
+            max_delay_ms: default_max_delay_ms(),
+            jitter: default_jitter(),
+            give_up_after: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay before retry number `attempt` (starting at 1), without jitter
+    pub fn base_delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(64) as i32;
+        let delay_ms = self.initial_delay_ms as f64 * self.multiplier.max(1.0).powi(exponent);
+        Duration::from_millis(delay_ms.min(self.max_delay_ms as f64) as u64)
+    }
+}
+
+/// Backoff state for one camera
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    policy: ReconnectPolicy,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(policy: ReconnectPolicy) -> Self {
+        Self { policy, attempt: 0 }
+    }
+
+    /// Consecutive failed attempts so far
+    pub fn attempts(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Forget previous failures once a connection is stable
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// Register a failure and return how long to wait, or `None` to give up
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        self.attempt += 1;
+        if let Some(limit) = self.policy.give_up_after {
+            if self.attempt > limit {
+                return None;
+            }
+        }
+
+        let base = self.policy.base_delay(self.attempt).as_secs_f64();
+        let jitter = self.policy.jitter.clamp(0.0, 1.0);
+        let factor = if jitter > 0.0 {
+            1.0 + rand::thread_rng().gen_range(-jitter..=jitter)
+        } else {
+            1.0
+        };
+        Some(Duration::from_secs_f64((base * factor).max(0.0)))
+    }
+}
+
+/// Connection state of a camera, reported on every transition
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Retrying { attempt: u32, delay: Duration },
+    GaveUp { attempts: u32 },
+    Stopped,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionState::Connecting => write!(f, "connecting"),
+            ConnectionState::Connected => write!(f, "connected"),
+            ConnectionState::Retrying { attempt, delay } => {
+                write!(f, "retrying in {:.1}s (attempt {})", delay.as_secs_f64(), attempt)
+            }
+            ConnectionState::GaveUp { attempts } => write!(f, "gave up after {} attempts", attempts),
+            ConnectionState::Stopped => write!(f, "stopped"),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Configuration hot reload
+//!
+//! [`ConfigWatcher`] notices when the configuration file changes and
+//! [`diff_cameras`] works out which cameras have to start, stop or restart, so
+//! `RecorderManager::apply_config` leaves unchanged cameras recording.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::rtsp::camera::CameraConfig;
+use crate::rtsp::{CaptureConfig, RTSPCapture};
+
+/// Cameras to start, stop and restart to go from one configuration to another
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigDiff {
+    pub added: Vec<CameraConfig>,
+    pub removed: Vec<String>,
+    pub changed: Vec<CameraConfig>,
+    pub unchanged: Vec<String>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare the cameras currently recording under `old_config` with the enabled
+/// cameras of `config`
+pub fn diff_cameras(
+    running: &[CameraConfig],
+    old_config: &CaptureConfig,
+    config: &CaptureConfig,
+) -> ConfigDiff {
+    let wanted: Vec<CameraConfig> = config
+        .resolved_cameras()
+        .into_iter()
+        .filter(|camera| camera.enabled)
+        .collect();
+    let running: HashMap<&str, &CameraConfig> = running
+        .iter()
+        .map(|camera| (camera.id.as_str(), camera))
+        .collect();
+
+    let mut diff = ConfigDiff::default();
+    for camera in &wanted {
+        match running.get(camera.id.as_str()) {
+            None => diff.added.push(camera.clone()),
+            Some(current) if camera_changed(current, old_config, camera, config) => {
+                diff.changed.push(camera.clone())
+            }
+            Some(_) => diff.unchanged.push(camera.id.clone()),
+        }
+    }
+
+    let mut removed: Vec<String> = running
+        .keys()
+        .filter(|id| !wanted.iter().any(|camera| camera.id == **id))
+        .map(|id| id.to_string())
+        .collect();
+    removed.sort();
+    diff.removed = removed;
+    diff
+}
+
+/// Whether a running camera has to restart to go from `old` to `new`.
+///
+/// Compares the settings the camera effectively records with, its own and the
+/// global ones it inherits, so edits that do not reach it (e.g. another
+/// camera's options or `hooks`) leave it recording. A camera whose capture
+/// cannot be built (e.g. an unreadable secrets file) counts as changed.
+pub fn camera_changed(
+    old: &CameraConfig,
+    old_config: &CaptureConfig,
+    new: &CameraConfig,
+    new_config: &CaptureConfig,
+) -> bool {
+    let capture = |camera: &CameraConfig, config: &CaptureConfig| {
+        RTSPCapture::from_camera(camera, config, config.preview_enabled())
+    };
+    match (capture(old, old_config), capture(new, new_config)) {
+        (Ok(old), Ok(new)) => !old.same_recording_settings(&new),
+        _ => true,
+    }
+}
+
+/// Load a capture configuration file and validate its cameras
+pub fn load_config(path: &Path) -> Result<CaptureConfig, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let config: CaptureConfig = serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+    for camera in config.resolved_cameras() {
+        camera
+            .validate()
+            .map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+    }
+    Ok(config)
+}
+
+/// Polls a configuration file for changes
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    /// Modification time and size at the last check
+    version: Option<(SystemTime, u64)>,
+}
+
+impl ConfigWatcher {
+    /// Watch `path`; its current contents count as already loaded
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            version: file_version(path),
+        }
+    }
+
+    /// The new configuration if the file changed since the last call.
+    ///
+    /// A file that does not parse (e.g. while an editor is still writing it)
+    /// is reported once and picked up again on its next change.
+    pub fn poll(&mut self) -> Option<Result<CaptureConfig, String>> {
+        let version = file_version(&self.path);
+        if version.is_none() || version == self.version {
+            return None;
+        }
+        self.version = version;
+        Some(load_config(&self.path))
+    }
+}
+
+fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
@@ -0,0 +1,294 @@
+//! Retention policy for recorded segments
+//!
+//! Deletes the oldest segments of each configured camera's directory once they
+//! pass a maximum age or a camera (or all cameras together) exceed a byte
+//! quota. Quotas count segment files only; catalogs, playlists and other
+//! directories below the output directory are left alone. Timestamped
+//! snapshots have their own age and count limits. The newest
+//! segment of every camera is never removed since FFmpeg or the OpenCV writer
+//! may still be writing to it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::rtsp::camera::{CameraConfig, CameraSource};
+use crate::rtsp::catalog::Catalog;
+use crate::rtsp::shutdown::ShutdownSignal;
+use crate::rtsp::snapshot::is_snapshot_file;
+
+/// Retention limits, any of which may be left unset
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    /// Delete segments older than this many hours
+    #[serde(default)]
+    pub max_age_hours: Option<u64>,
+    /// Maximum bytes of segments kept per camera
+    #[serde(default)]
+    pub max_camera_bytes: Option<u64>,
+    /// Maximum bytes of segments kept across all cameras
+    #[serde(default)]
+    pub max_total_bytes: Option<u64>,
+    /// Maximum number of timestamped snapshots kept per camera
+    #[serde(default)]
+    pub max_snapshots: Option<usize>,
+    /// Seconds between retention passes
+    #[serde(default = "default_check_interval_secs")]
+    pub check_interval_secs: u64,
+}
+
+fn default_check_interval_secs() -> u64 {
+    60
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age_hours: None,
+            max_camera_bytes: None,
+            max_total_bytes: None,
+            max_snapshots: None,
+            check_interval_secs: default_check_interval_secs(),
+        }
+    }
+}
+
+/// A recorded segment file considered for deletion
+#[derive(Debug, Clone)]
+struct SegmentFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Outcome of a single retention pass
+#[derive(Debug, Default)]
+pub struct RetentionReport {
+    pub removed: Vec<PathBuf>,
+    pub bytes_freed: u64,
+    pub errors: Vec<String>,
+}
+
+/// Applies a [`RetentionPolicy`] to the camera directories of an output directory
+pub struct RetentionManager {
+    output_dir: PathBuf,
+    policy: RetentionPolicy,
+    cameras: CameraSource,
+}
+
+impl RetentionManager {
+    /// Manager for the directories of the cameras returned by `cameras`
+    pub fn new<F>(output_dir: impl Into<PathBuf>, policy: RetentionPolicy, cameras: F) -> Self
+    where
+        F: Fn() -> Vec<CameraConfig> + Send + 'static,
+    {
+        Self {
+            output_dir: output_dir.into(),
+            policy,
+            cameras: Box::new(cameras),
+        }
+    }
+
+    /// Run retention passes on a background thread until `shutdown` is triggered
+    pub fn spawn(self, shutdown: ShutdownSignal) -> thread::JoinHandle<()> {
+        thread::spawn(move || loop {
+            match self.run_once() {
+                Ok(report) => {
+                    if !report.removed.is_empty() {
+                        println!(
+                            "Retention: removed {} segment(s), freed {} bytes",
+                            report.removed.len(),
+                            report.bytes_freed
+                        );
+                    }
+                    for error in &report.errors {
+                        eprintln!("Retention: {}", error);
+                    }
+                }
+                Err(e) => eprintln!(
+                    "Retention pass failed for {}: {}",
+                    self.output_dir.display(),
+                    e
+                ),
+            }
+            if shutdown.sleep(Duration::from_secs(self.policy.check_interval_secs.max(1))) {
+                break;
+            }
+        })
+    }
+
+    /// Run a single retention pass over every configured camera's directory
+    pub fn run_once(&self) -> io::Result<RetentionReport> {
+        let mut report = RetentionReport::default();
+        if !self.output_dir.is_dir() {
+            return Ok(report);
+        }
+
+        // Deletable segments per camera, oldest first, newest segment excluded,
+        // and the size of that newest segment
+        let mut camera_dirs: Vec<PathBuf> = (self.cameras)()
+            .iter()
+            .map(|camera| self.output_dir.join(camera.directory_name()))
+            .filter(|path| path.is_dir())
+            .collect();
+        camera_dirs.sort();
+        camera_dirs.dedup();
+
+        let mut cameras: Vec<(PathBuf, Vec<SegmentFile>, u64)> = Vec::new();
+        for path in camera_dirs {
+            let mut segments = list_files(&path, is_segment_file)?;
+            // Newest segment may still be open
+            let newest_size = segments.pop().map(|segment| segment.size).unwrap_or(0);
+            cameras.push((path, segments, newest_size));
+        }
+
+        let now = SystemTime::now();
+        let max_age = self
+            .policy
+            .max_age_hours
+            .map(|hours| Duration::from_secs(hours * 3600));
+
+        for (camera_dir, segments, newest_size) in cameras.iter_mut() {
+            if let Some(max_age) = max_age {
+                segments.retain(|segment| {
+                    let age = now.duration_since(segment.modified).unwrap_or_default();
+                    if age > max_age {
+                        remove_segment(segment, "max age exceeded", &mut report);
+                        false
+                    } else {
+                        true
+                    }
+                });
+            }
+
+            // Timestamped snapshots follow the same age limit and their own count
+            if max_age.is_some() || self.policy.max_snapshots.is_some() {
+                let mut snapshots = list_files(camera_dir, is_snapshot_file)?;
+                if let Some(max_age) = max_age {
+                    snapshots.retain(|snapshot| {
+                        let age = now.duration_since(snapshot.modified).unwrap_or_default();
+                        if age > max_age {
+                            remove_segment(snapshot, "max age exceeded", &mut report);
+                            false
+                        } else {
+                            true
+                        }
+                    });
+                }
+                if let Some(max_snapshots) = self.policy.max_snapshots {
+                    let excess = snapshots.len().saturating_sub(max_snapshots);
+                    for snapshot in &snapshots[..excess] {
+                        remove_segment(snapshot, "snapshot limit exceeded", &mut report);
+                    }
+                }
+            }
+
+            if let Some(max_camera_bytes) = self.policy.max_camera_bytes {
+                let mut total = *newest_size + segments_size(segments);
+                while total > max_camera_bytes && !segments.is_empty() {
+                    let segment = segments.remove(0);
+                    if remove_segment(&segment, "camera quota exceeded", &mut report) {
+                        total = total.saturating_sub(segment.size);
+                    }
+                }
+            }
+        }
+
+        if let Some(max_total_bytes) = self.policy.max_total_bytes {
+            let mut total: u64 = cameras
+                .iter()
+                .map(|(_, segments, newest_size)| newest_size + segments_size(segments))
+                .sum();
+            let mut remaining: Vec<SegmentFile> = cameras
+                .into_iter()
+                .flat_map(|(_, segments, _)| segments)
+                .collect();
+            remaining.sort_by_key(|segment| segment.modified);
+            for segment in remaining {
+                if total <= max_total_bytes {
+                    break;
+                }
+                if remove_segment(&segment, "total quota exceeded", &mut report) {
+                    total = total.saturating_sub(segment.size);
+                }
+            }
+        }
+
+        // Keep the catalogs in step with the files on disk
+        let mut removed: BTreeMap<&Path, Vec<PathBuf>> = BTreeMap::new();
+        for path in &report.removed {
+            if let Some(camera_dir) = path.parent() {
+                removed.entry(camera_dir).or_default().push(path.clone());
+            }
+        }
+        let mut errors = Vec::new();
+        for (camera_dir, paths) in removed {
+            if let Err(e) = Catalog::for_camera_dir(camera_dir).remove(&paths) {
+                errors.push(format!("Failed to update catalog in {}: {}", camera_dir.display(), e));
+            }
+        }
+        report.errors.extend(errors);
+
+        Ok(report)
+    }
+}
+
+/// Check whether a file name belongs to a recorded segment
+pub fn is_segment_file(name: &str) -> bool {
+    (name.starts_with("segment_") && name.ends_with(".mp4"))
+        || (name.starts_with("hls_") && (name.ends_with(".ts") || name.ends_with(".m4s")))
+}
+
+/// List the files of a camera directory whose name matches `filter`, oldest first
+fn list_files(camera_dir: &Path, filter: fn(&str) -> bool) -> io::Result<Vec<SegmentFile>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(camera_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if !filter(&name.to_string_lossy()) {
+            continue;
+        }
+        // Files can vanish mid-scan (e.g. HLS segments deleted by FFmpeg)
+        let metadata = match entry.metadata() {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => continue,
+        };
+        segments.push(SegmentFile {
+            path: entry.path(),
+            size: metadata.len(),
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        });
+    }
+    segments.sort_by(|a, b| a.modified.cmp(&b.modified).then_with(|| a.path.cmp(&b.path)));
+    Ok(segments)
+}
+
+fn segments_size(segments: &[SegmentFile]) -> u64 {
+    segments.iter().map(|segment| segment.size).sum()
+}
+
+fn remove_segment(segment: &SegmentFile, reason: &str, report: &mut RetentionReport) -> bool {
+    match fs::remove_file(&segment.path) {
+        Ok(_) => {
+            println!(
+                "Retention: removed {} ({} bytes, {})",
+                segment.path.display(),
+                segment.size,
+                reason
+            );
+            report.removed.push(segment.path.clone());
+            report.bytes_freed += segment.size;
+            true
+        }
+        Err(e) => {
+            report
+                .errors
+                .push(format!("Failed to remove {}: {}", segment.path.display(), e));
+            false
+        }
+    }
+}
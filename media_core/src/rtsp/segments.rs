@@ -0,0 +1,169 @@
+//! Detection and post-processing of finished segments
+//!
+//! FFmpeg does not tell us when it closes a segment, so [`SegmentTracker`]
+//! watches the camera directory: once a newer segment appears, every older
+//! one is complete. Finished segments are handed to a [`SegmentWorker`]
+//! thread so hashing, probing and hooks never stall the capture loop.
+
+use chrono::{DateTime, Local};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::rtsp::catalog::{describe_segment, Catalog};
+use crate::rtsp::hooks::{HookDispatcher, HooksConfig};
+use crate::rtsp::retention::is_segment_file;
+
+/// A segment the recorder has stopped writing to
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosedSegment {
+    pub path: PathBuf,
+    /// Wall-clock start, when the recorder tracked it
+    pub start: Option<DateTime<Local>>,
+    /// Wall-clock end, when the recorder tracked it
+    pub end: Option<DateTime<Local>>,
+}
+
+impl ClosedSegment {
+    pub fn from_path(path: PathBuf) -> Self {
+        Self {
+            path,
+            start: None,
+            end: None,
+        }
+    }
+}
+
+/// Finds segments FFmpeg has finished by scanning the camera directory
+#[derive(Debug)]
+pub struct SegmentTracker {
+    camera_dir: PathBuf,
+    known: HashSet<PathBuf>,
+    current: Option<PathBuf>,
+}
+
+impl SegmentTracker {
+    /// Start tracking; segments already on disk are treated as handled
+    pub fn new(camera_dir: &Path) -> Self {
+        let known = list_segments(camera_dir).into_iter().collect();
+        Self {
+            camera_dir: camera_dir.to_path_buf(),
+            known,
+            current: None,
+        }
+    }
+
+    /// Segments closed since the last call. The newest file is still being written.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut segments = list_segments(&self.camera_dir);
+        self.current = segments.pop();
+        self.take_new(segments)
+    }
+
+    /// All remaining segments, including the newest, once the recorder has stopped
+    pub fn flush(&mut self) -> Vec<PathBuf> {
+        let segments = list_segments(&self.camera_dir);
+        self.current = None;
+        self.take_new(segments)
+    }
+
+    /// Segment being written as of the last poll
+    pub fn current(&self) -> Option<&Path> {
+        self.current.as_deref()
+    }
+
+    fn take_new(&mut self, segments: Vec<PathBuf>) -> Vec<PathBuf> {
+        segments
+            .into_iter()
+            .filter(|path| self.known.insert(path.clone()))
+            .collect()
+    }
+}
+
+/// Segment files of a camera directory, oldest first (names embed the start time)
+pub fn list_segments(camera_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(camera_dir) else {
+        return Vec::new();
+    };
+
+    let mut segments: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .file_name()
+                    .is_some_and(|name| is_segment_file(&name.to_string_lossy()))
+        })
+        .collect();
+    segments.sort();
+    segments
+}
+
+/// Background thread processing finished segments of one camera in order
+pub struct SegmentWorker {
+    sender: Option<mpsc::Sender<ClosedSegment>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl SegmentWorker {
+    pub fn spawn(camera_id: String, catalog: Option<Catalog>, hooks: Option<HooksConfig>) -> Self {
+        let (sender, receiver) = mpsc::channel::<ClosedSegment>();
+
+        let handle = thread::spawn(move || {
+            let mut hooks = hooks.map(HookDispatcher::spawn);
+
+            for segment in receiver {
+                if catalog.is_none() && hooks.is_none() {
+                    continue;
+                }
+
+                let entry = match describe_segment(&camera_id, &segment.path, segment.start, segment.end) {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        eprintln!("Failed to describe segment {}: {}", segment.path.display(), e);
+                        continue;
+                    }
+                };
+
+                if let Some(catalog) = &catalog {
+                    if let Err(e) = catalog.append(&entry) {
+                        eprintln!(
+                            "Failed to write catalog entry for {}: {}",
+                            segment.path.display(),
+                            e
+                        );
+                    }
+                }
+                if let Some(hooks) = &hooks {
+                    hooks.dispatch(entry);
+                }
+            }
+
+            if let Some(hooks) = &mut hooks {
+                hooks.finish();
+            }
+        });
+
+        Self {
+            sender: Some(sender),
+            handle: Some(handle),
+        }
+    }
+
+    pub fn submit(&self, segment: ClosedSegment) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(segment);
+        }
+    }
+
+    /// Process the queued segments and stop the thread
+    pub fn finish(&mut self) {
+        self.sender.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
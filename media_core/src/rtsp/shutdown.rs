@@ -0,0 +1,77 @@
+//! Shutdown signalling for capture threads
+//!
+//! A [`ShutdownSignal`] is a cloneable flag shared by every `RTSPCapture`.
+//! Once triggered (by SIGINT/SIGTERM or programmatically) the capture loops
+//! stop, let FFmpeg finalize its segment and release the OpenCV writer.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Interval used to poll the flag while sleeping
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownSignal {
+    flag: Arc<AtomicBool>,
+    /// Flag of the signal this one was derived from with [`ShutdownSignal::child`]
+    parent: Option<Arc<AtomicBool>>,
+}
+
+impl ShutdownSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a signal triggered by SIGINT or SIGTERM.
+    ///
+    /// A second signal while shutdown is in progress terminates the process
+    /// immediately.
+    pub fn install() -> io::Result<Self> {
+        let signal = Self::new();
+        for sig in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+            signal_hook::flag::register_conditional_shutdown(sig, 1, Arc::clone(&signal.flag))?;
+            signal_hook::flag::register(sig, Arc::clone(&signal.flag))?;
+        }
+        Ok(signal)
+    }
+
+    /// Signal that can be triggered on its own and also fires with `self`,
+    /// e.g. to stop one camera while SIGINT still stops all of them
+    pub fn child(&self) -> Self {
+        Self {
+            flag: Arc::new(AtomicBool::new(false)),
+            parent: Some(Arc::clone(&self.flag)),
+        }
+    }
+
+    /// Request shutdown
+    pub fn trigger(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.load(Ordering::SeqCst))
+    }
+
+    /// Sleep for `duration`, returning early with `true` if shutdown is requested
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        loop {
+            if self.is_triggered() {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Periodic JPEG snapshots next to the recordings
+//!
+//! FFmpeg writes them through a second output that decodes only keyframes;
+//! the OpenCV path encodes the decoded frame directly.
+
+use chrono::Local;
+use opencv::{core, core::Vector, imgcodecs, imgproc, prelude::*, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// File overwritten with the newest snapshot in `latest` mode
+pub const LATEST_SNAPSHOT_NAME: &str = "latest.jpg";
+
+/// Whether snapshots overwrite one file or accumulate
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotMode {
+    /// Overwrite `latest.jpg`
+    #[default]
+    Latest,
+    /// Write `snapshot_%Y%m%d_%H%M%S.jpg` files
+    Timestamped,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotConfig {
+    /// Seconds between snapshots
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default)]
+    pub mode: SnapshotMode,
+    /// Scale snapshots to this width, keeping the aspect ratio
+    #[serde(default)]
+    pub width: Option<u32>,
+}
+
+fn default_interval_secs() -> u64 {
+    10
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_interval_secs(),
+            mode: SnapshotMode::default(),
+            width: None,
+        }
+    }
+}
+
+/// Check whether a file name belongs to a timestamped snapshot
+pub fn is_snapshot_file(name: &str) -> bool {
+    name.starts_with("snapshot_") && name.ends_with(".jpg")
+}
+
+/// FFmpeg arguments for a second output writing snapshots into `camera_dir`
+pub fn snapshot_output_args(camera_dir: &Path, config: &SnapshotConfig) -> Vec<String> {
+    let mut filter = format!("fps=1/{}", config.interval_secs.max(1));
+    if let Some(width) = config.width {
+        filter.push_str(&format!(",scale={}:-2", width));
+    }
+
+    let mut args: Vec<String> = [
+        "-map", "0:v:0", "-an", "-vf", filter.as_str(), "-q:v", "3", "-f", "image2",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+
+    match config.mode {
+        SnapshotMode::Latest => {
+            args.extend(["-update".to_string(), "1".to_string()]);
+            args.push(camera_dir.join(LATEST_SNAPSHOT_NAME).to_string_lossy().to_string());
+        }
+        SnapshotMode::Timestamped => {
+            args.extend(["-strftime".to_string(), "1".to_string()]);
+            args.push(
+                camera_dir
+                    .join("snapshot_%Y%m%d_%H%M%S.jpg")
+                    .to_string_lossy()
+                    .to_string(),
+            );
+        }
+    }
+    args
+}
+
+/// Writes snapshots of decoded frames for the OpenCV path
+pub struct SnapshotWriter {
+    config: SnapshotConfig,
+    last_written: Option<Instant>,
+    scaled: Mat,
+}
+
+impl SnapshotWriter {
+    pub fn new(config: SnapshotConfig) -> Self {
+        Self {
+            config,
+            last_written: None,
+            scaled: Mat::default(),
+        }
+    }
+
+    /// Write `frame` if the interval has passed since the previous snapshot
+    pub fn maybe_write(&mut self, camera_dir: &Path, frame: &Mat) -> Result<()> {
+        let interval = Duration::from_secs(self.config.interval_secs.max(1));
+        if self
+            .last_written
+            .is_some_and(|last| last.elapsed() < interval)
+        {
+            return Ok(());
+        }
+        self.last_written = Some(Instant::now());
+
+        let image = match self.config.width {
+            Some(width) if frame.cols() > 0 => {
+                let scale = width as f64 / frame.cols() as f64;
+                imgproc::resize(
+                    frame,
+                    &mut self.scaled,
+                    core::Size::new(0, 0),
+                    scale,
+                    scale,
+                    imgproc::INTER_AREA,
+                )?;
+                &self.scaled
+            }
+            _ => frame,
+        };
+
+        let params = Vector::from_slice(&[imgcodecs::IMWRITE_JPEG_QUALITY, 90]);
+        match self.config.mode {
+            SnapshotMode::Latest => {
+                // Write next to the target and rename so readers never see a partial file
+                let tmp_path = camera_dir.join("latest.tmp.jpg");
+                imgcodecs::imwrite(&tmp_path.to_string_lossy(), image, &params)?;
+                fs::rename(&tmp_path, camera_dir.join(LATEST_SNAPSHOT_NAME)).map_err(|e| {
+                    opencv::Error::new(
+                        opencv::core::StsError,
+                        &format!("Failed to replace {}: {}", LATEST_SNAPSHOT_NAME, e),
+                    )
+                })?;
+            }
+            SnapshotMode::Timestamped => {
+                let path: PathBuf = camera_dir.join(format!(
+                    "snapshot_{}.jpg",
+                    Local::now().format("%Y%m%d_%H%M%S")
+                ));
+                imgcodecs::imwrite(&path.to_string_lossy(), image, &params)?;
+            }
+        }
+        Ok(())
+    }
+}
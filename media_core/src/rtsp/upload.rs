@@ -0,0 +1,731 @@
+//! Upload of finished segments to S3-compatible object storage
+//!
+//! A background thread scans the directory of every configured camera like
+//! the retention manager does and uploads closed segments (all but the newest
+//! of a camera that is recording) with Signature V4. Large files go through multipart upload, every request is
+//! retried and the total upload rate can be capped. Uploaded file names are
+//! recorded in a `.uploaded` ledger per camera, or the local file is deleted
+//! once the object size has been verified with a HEAD request.
+
+use chrono::{DateTime, Local, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::rtsp::camera::{CameraConfig, CameraSource};
+use crate::rtsp::catalog::{start_time_from_file_name, Catalog};
+use crate::rtsp::retention::is_segment_file;
+use crate::rtsp::shutdown::ShutdownSignal;
+
+/// Ledger of uploaded file names inside a camera directory
+pub const UPLOAD_LEDGER_NAME: &str = ".uploaded";
+
+/// Smallest part size S3 accepts (except for the last part)
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UploadConfig {
+    /// Endpoint URL, e.g. `https://s3.eu-central-1.amazonaws.com` or `http://minio:9000`
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    /// Environment variable holding the access key id
+    #[serde(default = "default_access_key_env")]
+    pub access_key_env: String,
+    /// Environment variable holding the secret access key
+    #[serde(default = "default_secret_key_env")]
+    pub secret_key_env: String,
+    /// Object key with `{camera}` (the camera id), `{date}`, `{hour}`, `{year}`, `{month}`, `{day}` and `{file}`
+    #[serde(default = "default_key_template")]
+    pub key_template: String,
+    /// Address the bucket as `<endpoint>/<bucket>` instead of `<bucket>.<endpoint host>`
+    #[serde(default = "default_path_style")]
+    pub path_style: bool,
+    /// Files of at least this size are uploaded in parts
+    #[serde(default = "default_multipart_threshold_bytes")]
+    pub multipart_threshold_bytes: u64,
+    #[serde(default = "default_part_size_bytes")]
+    pub part_size_bytes: u64,
+    /// Retries per request
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+    /// Upper bound for the upload rate across all cameras
+    #[serde(default)]
+    pub max_bytes_per_sec: Option<u64>,
+    /// Delete the local file once the uploaded object size has been verified
+    #[serde(default)]
+    pub delete_after_upload: bool,
+    /// Seconds between directory scans
+    #[serde(default = "default_scan_interval_secs")]
+    pub scan_interval_secs: u64,
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_access_key_env() -> String {
+    "AWS_ACCESS_KEY_ID".to_string()
+}
+
+fn default_secret_key_env() -> String {
+    "AWS_SECRET_ACCESS_KEY".to_string()
+}
+
+fn default_key_template() -> String {
+    "{camera}/{date}/{hour}/{file}".to_string()
+}
+
+fn default_path_style() -> bool {
+    true
+}
+
+fn default_multipart_threshold_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_part_size_bytes() -> u64 {
+    16 * 1024 * 1024
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+fn default_retry_delay_ms() -> u64 {
+    2000
+}
+
+fn default_scan_interval_secs() -> u64 {
+    30
+}
+
+/// Outcome of one upload pass
+#[derive(Debug, Default)]
+pub struct UploadReport {
+    pub uploaded: Vec<PathBuf>,
+    pub bytes_uploaded: u64,
+    pub errors: Vec<String>,
+}
+
+/// Render an object key from the template
+pub fn render_key(template: &str, camera: &str, file_name: &str, time: DateTime<Local>) -> String {
+    template
+        .replace("{camera}", camera)
+        .replace("{date}", &time.format("%Y-%m-%d").to_string())
+        .replace("{hour}", &time.format("%H").to_string())
+        .replace("{year}", &time.format("%Y").to_string())
+        .replace("{month}", &time.format("%m").to_string())
+        .replace("{day}", &time.format("%d").to_string())
+        .replace("{file}", file_name)
+}
+
+/// Caps the number of bytes read per second
+#[derive(Debug)]
+pub struct Throttle {
+    bytes_per_sec: Option<u64>,
+    started: Instant,
+    sent: u64,
+}
+
+impl Throttle {
+    pub fn new(bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            bytes_per_sec,
+            started: Instant::now(),
+            sent: 0,
+        }
+    }
+
+    /// Account for `bytes` and sleep until they fit into the rate
+    pub fn consume(&mut self, bytes: u64) {
+        let Some(rate) = self.bytes_per_sec.filter(|rate| *rate > 0) else {
+            return;
+        };
+        // Start a new window after idle periods so bursts are not saved up
+        if self.started.elapsed() > Duration::from_secs(10) {
+            self.started = Instant::now();
+            self.sent = 0;
+        }
+        self.sent += bytes;
+        let due = Duration::from_secs_f64(self.sent as f64 / rate as f64);
+        let elapsed = self.started.elapsed();
+        if due > elapsed {
+            thread::sleep(due - elapsed);
+        }
+    }
+}
+
+/// Reader that goes through a [`Throttle`]
+pub struct ThrottledReader<'a, R> {
+    inner: R,
+    throttle: &'a mut Throttle,
+}
+
+impl<'a, R: Read> ThrottledReader<'a, R> {
+    pub fn new(inner: R, throttle: &'a mut Throttle) -> Self {
+        Self { inner, throttle }
+    }
+}
+
+impl<R: Read> Read for ThrottledReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Small reads keep the rate smooth
+        let len = buf.len().min(64 * 1024);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.throttle.consume(read as u64);
+        Ok(read)
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Signature V4 signing key for a date (`YYYYMMDD`), region and service
+pub fn signing_key(secret_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let k_date = hmac(format!("AWS4{}", secret_key).as_bytes(), date);
+    let k_region = hmac(&k_date, region);
+    let k_service = hmac(&k_region, service);
+    hmac(&k_service, "aws4_request")
+}
+
+/// Percent-encode for S3 paths and query strings
+pub fn uri_encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric()
+            || matches!(byte, b'-' | b'.' | b'_' | b'~')
+            || (keep_slash && byte == b'/')
+        {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Minimal S3 client covering the calls needed to upload segments
+pub struct S3Client {
+    scheme: String,
+    host: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    path_style: bool,
+    agent: ureq::Agent,
+}
+
+impl S3Client {
+    pub fn new(config: &UploadConfig) -> io::Result<Self> {
+        let env = |name: &str| {
+            std::env::var(name).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Environment variable {} is not set", name),
+                )
+            })
+        };
+
+        let (scheme, rest) = config.endpoint.split_once("://").ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid upload endpoint {}", config.endpoint),
+            )
+        })?;
+        let authority = rest.split('/').next().unwrap_or_default();
+        // The Host header leaves out default ports, so the signature must too
+        let host = match scheme {
+            "http" => authority.strip_suffix(":80").unwrap_or(authority),
+            "https" => authority.strip_suffix(":443").unwrap_or(authority),
+            _ => authority,
+        };
+
+        Ok(Self {
+            scheme: scheme.to_string(),
+            host: host.to_string(),
+            bucket: config.bucket.clone(),
+            region: config.region.clone(),
+            access_key: env(&config.access_key_env)?,
+            secret_key: env(&config.secret_key_env)?,
+            path_style: config.path_style,
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(Duration::from_secs(10))
+                .timeout_read(Duration::from_secs(60))
+                .build(),
+        })
+    }
+
+    /// Build and sign a request for `key`
+    fn request(&self, method: &str, key: &str, query: &[(&str, String)]) -> ureq::Request {
+        let (host, path) = if self.path_style {
+            (
+                self.host.clone(),
+                format!("/{}/{}", uri_encode(&self.bucket, false), uri_encode(key, true)),
+            )
+        } else {
+            (format!("{}.{}", self.bucket, self.host), format!("/{}", uri_encode(key, true)))
+        };
+
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(name, value)| (uri_encode(name, false), uri_encode(value, false)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let now: DateTime<Utc> = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = "UNSIGNED-PAYLOAD";
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+            method, path, query, host, payload_hash, amz_date, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signature = hex(&hmac(
+            &signing_key(&self.secret_key, &date, &self.region, "s3"),
+            &string_to_sign,
+        ));
+
+        let mut url = format!("{}://{}{}", self.scheme, host, path);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query);
+        }
+
+        self.agent
+            .request(method, &url)
+            .set("Host", &host)
+            .set("x-amz-date", &amz_date)
+            .set("x-amz-content-sha256", payload_hash)
+            .set(
+                "Authorization",
+                &format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+                    self.access_key, scope, signature
+                ),
+            )
+    }
+
+    pub fn put_object(&self, key: &str, body: impl Read, len: u64) -> io::Result<()> {
+        self.request("PUT", key, &[])
+            .set("Content-Length", &len.to_string())
+            .send(body)
+            .map(|_| ())
+            .map_err(request_error)
+    }
+
+    /// Size of an object, `None` if it does not exist
+    pub fn head_object(&self, key: &str) -> io::Result<Option<u64>> {
+        match self.request("HEAD", key, &[]).call() {
+            Ok(response) => Ok(response
+                .header("Content-Length")
+                .and_then(|len| len.parse().ok())),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(request_error(e)),
+        }
+    }
+
+    pub fn create_multipart_upload(&self, key: &str) -> io::Result<String> {
+        let body = self
+            .request("POST", key, &[("uploads", String::new())])
+            .send_bytes(&[])
+            .map_err(request_error)?
+            .into_string()?;
+        xml_element(&body, "UploadId").ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "Missing UploadId in response")
+        })
+    }
+
+    /// Upload one part and return its ETag
+    pub fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        body: impl Read,
+        len: u64,
+    ) -> io::Result<String> {
+        let response = self
+            .request(
+                "PUT",
+                key,
+                &[
+                    ("partNumber", part_number.to_string()),
+                    ("uploadId", upload_id.to_string()),
+                ],
+            )
+            .set("Content-Length", &len.to_string())
+            .send(body)
+            .map_err(request_error)?;
+        response
+            .header("ETag")
+            .map(str::to_string)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing ETag for part"))
+    }
+
+    pub fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        etags: &[String],
+    ) -> io::Result<()> {
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (index, etag) in etags.iter().enumerate() {
+            body.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                index + 1,
+                etag
+            ));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+
+        let response = self
+            .request("POST", key, &[("uploadId", upload_id.to_string())])
+            .set("Content-Type", "application/xml")
+            .send_string(&body)
+            .map_err(request_error)?
+            .into_string()?;
+        // S3 may report a failed completion inside a 200 response
+        if response.contains("<Error>") {
+            return Err(io::Error::other(format!(
+                "Completing multipart upload failed: {}",
+                response
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> io::Result<()> {
+        self.request("DELETE", key, &[("uploadId", upload_id.to_string())])
+            .call()
+            .map(|_| ())
+            .map_err(request_error)
+    }
+}
+
+fn request_error(error: ureq::Error) -> io::Error {
+    match error {
+        ureq::Error::Status(code, response) => io::Error::other(format!(
+            "HTTP {}: {}",
+            code,
+            response.into_string().unwrap_or_default()
+        )),
+        ureq::Error::Transport(transport) => io::Error::other(transport.to_string()),
+    }
+}
+
+/// Text of the first `<name>` element
+fn xml_element(xml: &str, name: &str) -> Option<String> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    Some(xml[start..end].to_string())
+}
+
+/// Ships closed segments of every camera directory to object storage
+pub struct Uploader {
+    output_dir: PathBuf,
+    config: UploadConfig,
+    client: S3Client,
+    throttle: Throttle,
+    cameras: CameraSource,
+    /// Whether a camera is recording, so its newest segment may still be open.
+    /// Without it every camera counts as recording, also in the last pass.
+    pub recording: Option<Box<dyn Fn(&str) -> bool + Send>>,
+}
+
+impl Uploader {
+    /// Uploader for the directories of the cameras returned by `cameras`
+    pub fn new<F>(output_dir: impl Into<PathBuf>, config: UploadConfig, cameras: F) -> io::Result<Self>
+    where
+        F: Fn() -> Vec<CameraConfig> + Send + 'static,
+    {
+        Ok(Self {
+            output_dir: output_dir.into(),
+            client: S3Client::new(&config)?,
+            throttle: Throttle::new(config.max_bytes_per_sec),
+            config,
+            cameras: Box::new(cameras),
+            recording: None,
+        })
+    }
+
+    /// Run upload passes on a background thread until `shutdown` is triggered.
+    /// Then wait for the cameras to stop recording and upload the segments they
+    /// finalized in a last pass.
+    pub fn spawn(mut self, shutdown: ShutdownSignal) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            let interval = Duration::from_secs(self.config.scan_interval_secs.max(1));
+            loop {
+                self.run_and_log();
+                if shutdown.sleep(interval) {
+                    break;
+                }
+            }
+
+            if let Some(recording) = &self.recording {
+                while (self.cameras)().iter().any(|camera| recording(&camera.id)) {
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+            self.run_and_log();
+        })
+    }
+
+    /// Run one pass and log its outcome
+    fn run_and_log(&mut self) {
+        match self.run_once() {
+            Ok(report) => {
+                if !report.uploaded.is_empty() || !report.errors.is_empty() {
+                    println!(
+                        "Upload: {} segment(s), {} bytes, {} error(s)",
+                        report.uploaded.len(),
+                        report.bytes_uploaded,
+                        report.errors.len()
+                    );
+                }
+                for error in &report.errors {
+                    eprintln!("Upload: {}", error);
+                }
+            }
+            Err(e) => eprintln!(
+                "Upload pass failed for {}: {}",
+                self.output_dir.display(),
+                e
+            ),
+        }
+    }
+
+    /// Upload every closed segment that has not been uploaded yet. Problems
+    /// with one camera or file are reported and the pass continues.
+    pub fn run_once(&mut self) -> io::Result<UploadReport> {
+        let mut report = UploadReport::default();
+        if !self.output_dir.is_dir() {
+            return Ok(report);
+        }
+
+        for camera in (self.cameras)() {
+            let camera_dir = self.output_dir.join(camera.directory_name());
+            if !camera_dir.is_dir() {
+                continue;
+            }
+            if let Err(e) = self.upload_camera(&camera.id, &camera_dir, &mut report) {
+                report
+                    .errors
+                    .push(format!("Failed to scan {}: {}", camera_dir.display(), e));
+            }
+        }
+        Ok(report)
+    }
+
+    fn upload_camera(
+        &mut self,
+        camera: &str,
+        camera_dir: &Path,
+        report: &mut UploadReport,
+    ) -> io::Result<()> {
+        let uploaded = load_ledger(camera_dir)?;
+
+        let mut segments: Vec<String> = fs::read_dir(camera_dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| is_segment_file(name))
+            .collect();
+        segments.sort();
+        if self.recording.as_ref().is_none_or(|recording| recording(camera)) {
+            segments.pop(); // Newest segment may still be open
+        }
+
+        for name in segments {
+            if uploaded.contains(&name) {
+                continue;
+            }
+            let path = camera_dir.join(&name);
+            match self.upload_segment(camera, &path) {
+                Ok(size) => {
+                    let recorded = if self.config.delete_after_upload {
+                        fs::remove_file(&path).and_then(|_| {
+                            Catalog::for_camera_dir(camera_dir)
+                                .remove(&[path.clone()])
+                                .map(|_| ())
+                        })
+                    } else {
+                        append_ledger(camera_dir, &name)
+                    };
+                    // Uploaded either way; without the record it is uploaded again next pass
+                    if let Err(e) = recorded {
+                        report.errors.push(format!(
+                            "Uploaded {} but failed to record it: {}",
+                            path.display(),
+                            e
+                        ));
+                    }
+                    report.bytes_uploaded += size;
+                    report.uploaded.push(path);
+                }
+                Err(e) => report
+                    .errors
+                    .push(format!("Failed to upload {}: {}", path.display(), e)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Upload one file and verify the stored size, returning it
+    pub fn upload_segment(&mut self, camera: &str, path: &Path) -> io::Result<u64> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let size = fs::metadata(path)?.len();
+        let time = start_time_from_file_name(path).unwrap_or_else(|| {
+            DateTime::<Local>::from(fs::metadata(path).and_then(|m| m.modified()).unwrap_or(SystemTime::now()))
+        });
+        let key = render_key(&self.config.key_template, camera, &name, time);
+
+        if size >= self.config.multipart_threshold_bytes.max(1) {
+            self.upload_multipart(path, &key, size)?;
+        } else {
+            let client = &self.client;
+            let throttle = &mut self.throttle;
+            retry(&self.config, || {
+                let file = File::open(path)?;
+                client.put_object(&key, ThrottledReader::new(file, &mut *throttle), size)
+            })?;
+        }
+
+        let stored = retry(&self.config, || self.client.head_object(&key))?;
+        if stored != Some(size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Uploaded size of {} is {:?}, expected {}", key, stored, size),
+            ));
+        }
+        println!("Uploaded {} to {}/{}", path.display(), self.config.bucket, key);
+        Ok(size)
+    }
+
+    fn upload_multipart(&mut self, path: &Path, key: &str, size: u64) -> io::Result<()> {
+        let part_size = self.config.part_size_bytes.max(MIN_PART_SIZE);
+        let client = &self.client;
+        let throttle = &mut self.throttle;
+        let config = &self.config;
+
+        let upload_id = retry(config, || client.create_multipart_upload(key))?;
+        let mut upload_parts = || {
+            let mut etags = Vec::new();
+            let mut offset = 0;
+            while offset < size {
+                let len = part_size.min(size - offset);
+                let part_number = etags.len() as u32 + 1;
+                let etag = retry(config, || {
+                    let mut file = File::open(path)?;
+                    file.seek(SeekFrom::Start(offset))?;
+                    let reader = ThrottledReader::new(file.take(len), &mut *throttle);
+                    client.upload_part(key, &upload_id, part_number, reader, len)
+                })?;
+                etags.push(etag);
+                offset += len;
+            }
+            retry(config, || client.complete_multipart_upload(key, &upload_id, &etags))
+        };
+
+        let result = upload_parts();
+        if result.is_err() {
+            let _ = client.abort_multipart_upload(key, &upload_id);
+        }
+        result
+    }
+}
+
+/// Run `request` until it succeeds or the retries are used up
+fn retry<T, F>(config: &UploadConfig, mut request: F) -> io::Result<T>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    let mut attempt = 0;
+    loop {
+        match request() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < config.max_retries => {
+                attempt += 1;
+                eprintln!(
+                    "Upload request failed (attempt {}/{}): {}",
+                    attempt,
+                    config.max_retries + 1,
+                    e
+                );
+                thread::sleep(Duration::from_millis(config.retry_delay_ms * attempt as u64));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// File names already uploaded from a camera directory, pruned of deleted files
+fn load_ledger(camera_dir: &Path) -> io::Result<HashSet<String>> {
+    let path = camera_dir.join(UPLOAD_LEDGER_NAME);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(e),
+    };
+
+    let names: Vec<&str> = contents.lines().filter(|line| !line.is_empty()).collect();
+    let existing: HashSet<String> = names
+        .iter()
+        .filter(|name| camera_dir.join(name).exists())
+        .map(|name| name.to_string())
+        .collect();
+    if existing.len() != names.len() {
+        // Retention removed some of them
+        let mut kept: Vec<&String> = existing.iter().collect();
+        kept.sort();
+        let mut file = File::create(&path)?;
+        for name in kept {
+            writeln!(file, "{}", name)?;
+        }
+    }
+    Ok(existing)
+}
+
+fn append_ledger(camera_dir: &Path, name: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(camera_dir.join(UPLOAD_LEDGER_NAME))?;
+    writeln!(file, "{}", name)
+}
@@ -0,0 +1,340 @@
+//! Segment integrity verification and repair
+//!
+//! A crash or power loss leaves the segment being written without its index:
+//! MP4 files lose the `moov` atom written on close, or end in the middle of a
+//! box. Broken segments can be remuxed with FFmpeg (which recovers files that
+//! are merely truncated) or moved into a `quarantine` directory next to the
+//! camera directories, so they no longer count as recordings of the camera.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::rtsp::catalog::Catalog;
+use crate::rtsp::segments::list_segments;
+
+/// Directory next to the camera directories that receives broken segments,
+/// with one subdirectory per camera
+pub const QUARANTINE_DIR_NAME: &str = "quarantine";
+
+/// Seconds decoded by the quick decode check
+const QUICK_DECODE_SECS: &str = "5";
+
+/// What is wrong with a segment
+#[derive(Debug, Clone, PartialEq)]
+pub enum SegmentIssue {
+    Empty,
+    Truncated(String),
+    MissingMoov,
+    Undecodable(String),
+}
+
+impl fmt::Display for SegmentIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SegmentIssue::Empty => write!(f, "zero-length file"),
+            SegmentIssue::Truncated(detail) => write!(f, "truncated: {}", detail),
+            SegmentIssue::MissingMoov => write!(f, "missing moov atom"),
+            SegmentIssue::Undecodable(detail) => write!(f, "undecodable: {}", detail),
+        }
+    }
+}
+
+/// What to do with broken segments
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VerifyAction {
+    /// Only report
+    #[default]
+    Report,
+    /// Remux with FFmpeg, quarantining segments that cannot be repaired
+    Repair,
+    /// Move into the quarantine directory
+    Quarantine,
+}
+
+/// How thoroughly segments are checked
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DecodeCheck {
+    /// Container structure only
+    #[default]
+    None,
+    /// Decode the first seconds
+    Quick,
+    /// Decode the whole file
+    Full,
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub checked: usize,
+    pub issues: Vec<(PathBuf, SegmentIssue)>,
+    pub repaired: Vec<PathBuf>,
+    pub quarantined: Vec<PathBuf>,
+    pub errors: Vec<String>,
+}
+
+/// Check one segment, returning the first problem found
+pub fn verify_segment(path: &Path, decode: DecodeCheck) -> io::Result<Option<SegmentIssue>> {
+    let len = fs::metadata(path)?.len();
+    if len == 0 {
+        return Ok(Some(SegmentIssue::Empty));
+    }
+
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    let structure = match extension {
+        "mp4" => check_mp4_structure(path)?,
+        "ts" => check_ts_structure(path, len)?,
+        // fMP4 fragments cannot be decoded without the playlist's init.mp4
+        _ => return Ok(None),
+    };
+    if structure.is_some() {
+        return Ok(structure);
+    }
+
+    Ok(match decode {
+        DecodeCheck::None => None,
+        DecodeCheck::Quick => check_decodable(path, Some(QUICK_DECODE_SECS)),
+        DecodeCheck::Full => check_decodable(path, None),
+    })
+}
+
+/// Walk the top-level MP4 boxes: every box must fit into the file and `moov` must exist
+pub fn check_mp4_structure(path: &Path) -> io::Result<Option<SegmentIssue>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut offset = 0u64;
+    let mut has_moov = false;
+
+    while offset < len {
+        if len - offset < 8 {
+            return Ok(Some(SegmentIssue::Truncated(format!(
+                "{} trailing bytes at offset {}",
+                len - offset,
+                offset
+            ))));
+        }
+
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+        let size32 = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let box_type = String::from_utf8_lossy(&header[4..8]).to_string();
+
+        let size = match size32 {
+            0 => len - offset, // Box extends to the end of the file
+            1 => {
+                let mut large = [0u8; 8];
+                if file.read_exact(&mut large).is_err() {
+                    return Ok(Some(SegmentIssue::Truncated(format!(
+                        "incomplete '{}' header at offset {}",
+                        box_type, offset
+                    ))));
+                }
+                u64::from_be_bytes(large)
+            }
+            size => size,
+        };
+
+        if size < 8 {
+            return Ok(Some(SegmentIssue::Truncated(format!(
+                "invalid '{}' box size {} at offset {}",
+                box_type, size, offset
+            ))));
+        }
+        if offset + size > len {
+            return Ok(Some(SegmentIssue::Truncated(format!(
+                "'{}' box at offset {} needs {} bytes, {} available",
+                box_type,
+                offset,
+                size,
+                len - offset
+            ))));
+        }
+
+        has_moov |= box_type == "moov";
+        offset += size;
+    }
+
+    Ok(if has_moov {
+        None
+    } else {
+        Some(SegmentIssue::MissingMoov)
+    })
+}
+
+/// MPEG-TS files are a sequence of 188 byte packets starting with 0x47
+fn check_ts_structure(path: &Path, len: u64) -> io::Result<Option<SegmentIssue>> {
+    let mut sync = [0u8; 1];
+    File::open(path)?.read_exact(&mut sync)?;
+    if sync[0] != 0x47 {
+        return Ok(Some(SegmentIssue::Undecodable(
+            "missing MPEG-TS sync byte".to_string(),
+        )));
+    }
+    if len % 188 != 0 {
+        return Ok(Some(SegmentIssue::Truncated(format!(
+            "last MPEG-TS packet has {} of 188 bytes",
+            len % 188
+        ))));
+    }
+    Ok(None)
+}
+
+/// Decode the file (or its first seconds) with FFmpeg
+fn check_decodable(path: &Path, duration: Option<&str>) -> Option<SegmentIssue> {
+    let mut command = Command::new("ffmpeg");
+    command.args(["-v", "error", "-xerror", "-i"]).arg(path);
+    if let Some(duration) = duration {
+        command.args(["-t", duration]);
+    }
+    let output = match command
+        .args(["-f", "null", "-"])
+        .stdin(Stdio::null())
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => return Some(SegmentIssue::Undecodable(format!("failed to run ffmpeg: {}", e))),
+    };
+
+    if output.status.success() {
+        None
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Some(SegmentIssue::Undecodable(
+            stderr.lines().last().unwrap_or("ffmpeg failed").to_string(),
+        ))
+    }
+}
+
+/// Remux a segment with FFmpeg and replace it if the result verifies
+pub fn repair_segment(path: &Path) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // Leading dot keeps the temporary file out of retention, catalog and upload scans
+    let repaired = path.with_file_name(format!(".repair_{}", name));
+
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-v", "error", "-y", "-err_detect", "ignore_err", "-i"])
+        .arg(path)
+        .args(["-c", "copy"]);
+    if name.ends_with(".mp4") {
+        command.args(["-movflags", "+faststart"]);
+    }
+    let output = command.arg(&repaired).stdin(Stdio::null()).output()?;
+
+    let result = if !output.status.success() {
+        Err(io::Error::other(format!(
+            "ffmpeg remux failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    } else {
+        match verify_segment(&repaired, DecodeCheck::None)? {
+            Some(issue) => Err(io::Error::other(format!("remuxed file is still broken: {}", issue))),
+            None => fs::rename(&repaired, path),
+        }
+    };
+
+    if result.is_err() {
+        let _ = fs::remove_file(&repaired);
+    }
+    result
+}
+
+/// Move a segment to `<output directory>/quarantine/<camera directory name>/`
+pub fn quarantine_segment(path: &Path) -> io::Result<PathBuf> {
+    let camera_dir = path.parent().unwrap_or(Path::new("."));
+    let camera_dir_name = camera_dir
+        .file_name()
+        .ok_or_else(|| io::Error::other(format!("{} has no camera directory", path.display())))?;
+    let quarantine_dir = camera_dir
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(QUARANTINE_DIR_NAME)
+        .join(camera_dir_name);
+    fs::create_dir_all(&quarantine_dir)?;
+    let target = quarantine_dir.join(path.file_name().unwrap_or_default());
+    fs::rename(path, &target)?;
+    Ok(target)
+}
+
+/// Verify segments and apply `action` to the broken ones
+pub fn verify_segments(
+    segments: &[PathBuf],
+    action: VerifyAction,
+    decode: DecodeCheck,
+) -> VerifyReport {
+    let mut report = VerifyReport::default();
+
+    for path in segments {
+        report.checked += 1;
+        let issue = match verify_segment(path, decode) {
+            Ok(Some(issue)) => issue,
+            Ok(None) => continue,
+            Err(e) => {
+                report.errors.push(format!("Failed to check {}: {}", path.display(), e));
+                continue;
+            }
+        };
+        eprintln!("Broken segment {}: {}", path.display(), issue);
+        report.issues.push((path.clone(), issue));
+
+        let quarantine = match action {
+            VerifyAction::Report => false,
+            VerifyAction::Quarantine => true,
+            VerifyAction::Repair => match repair_segment(path) {
+                Ok(_) => {
+                    println!("Repaired segment {}", path.display());
+                    report.repaired.push(path.clone());
+                    let camera_dir = path.parent().unwrap_or(Path::new("."));
+                    if let Err(e) = Catalog::for_camera_dir(camera_dir).refresh(path) {
+                        report.errors.push(format!(
+                            "Failed to update the catalog entry of {}: {}",
+                            path.display(),
+                            e
+                        ));
+                    }
+                    false
+                }
+                Err(e) => {
+                    eprintln!("Could not repair {}: {}", path.display(), e);
+                    true
+                }
+            },
+        };
+
+        if quarantine {
+            match quarantine_segment(path) {
+                Ok(target) => {
+                    println!("Quarantined {} to {}", path.display(), target.display());
+                    report.quarantined.push(target);
+                    let camera_dir = path.parent().unwrap_or(Path::new("."));
+                    if let Err(e) = Catalog::for_camera_dir(camera_dir).remove(&[path.clone()]) {
+                        report.errors.push(format!(
+                            "Failed to remove {} from the catalog: {}",
+                            path.display(),
+                            e
+                        ));
+                    }
+                }
+                Err(e) => report
+                    .errors
+                    .push(format!("Failed to quarantine {}: {}", path.display(), e)),
+            }
+        }
+    }
+
+    report
+}
+
+/// Startup check of the newest segment, the one a crash is most likely to have broken
+pub fn check_last_segment(camera_dir: &Path) -> VerifyReport {
+    match list_segments(camera_dir).pop() {
+        Some(last) => verify_segments(&[last], VerifyAction::Repair, DecodeCheck::Quick),
+        None => VerifyReport::default(),
+    }
+}
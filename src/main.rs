@@ -1,12 +1,20 @@
-use media_core::{CaptureConfig, RTSPCapture, SavingOption};
+use media_core::{shutdown_recorder, Catalog, ConfigWatcher, ControlServer, DecodeCheck, RecorderManager, RetentionManager, ShutdownSignal, Uploader, VerifyAction};
+use media_core::metrics::{render_capture_metrics, render_processing_metrics, MetricsServer};
+use media_core::segments::list_segments;
 use media_core::process::{create_video_processor, ProcessingMode, create_processor_with_mode};
+use media_core::probe::probe_source;
+use media_core::reload::load_config;
+use media_core::verify::verify_segments;
 use serde_json;
 use std::error::Error;
-use std::fs::File;
-use std::io::BufReader;
-use std::thread;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::env;
 
+/// Interval between checks of config.json for changes in rtsp mode
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
     
@@ -20,10 +28,48 @@ fn main() -> Result<(), Box<dyn Error>> {
         "process" => {
             if args.len() < 3 {
                 println!("Error: Process mode requires a config file path");
-                println!("Usage: cargo run process <config_file_path>");
+                println!("Usage: cargo run process <config_file_path> [--metrics <address>]");
                 return Ok(());
             }
-            run_process_mode(&args[2])?;
+            let metrics_bind = args
+                .iter()
+                .position(|arg| arg == "--metrics")
+                .and_then(|index| args.get(index + 1));
+            run_process_mode(&args[2], metrics_bind.map(String::as_str))?;
+        },
+        "probe" => {
+            if args.len() < 3 {
+                println!("Error: Probe mode requires a stream URL or file");
+                println!("Usage: cargo run probe <url|file> [--json]");
+                return Ok(());
+            }
+            run_probe_mode(&args[2], args[3..].iter().any(|arg| arg == "--json"))?;
+        },
+        "catalog" => {
+            if args.get(2).map(String::as_str) != Some("rebuild") {
+                println!("Error: Unknown catalog command");
+                println!("Usage: cargo run catalog rebuild");
+                return Ok(());
+            }
+            run_catalog_rebuild()?;
+        },
+        "verify" => {
+            let options = &args[2..];
+            let action = if options.iter().any(|arg| arg == "--repair") {
+                VerifyAction::Repair
+            } else if options.iter().any(|arg| arg == "--quarantine") {
+                VerifyAction::Quarantine
+            } else {
+                VerifyAction::Report
+            };
+            let decode = if options.iter().any(|arg| arg == "--full") {
+                DecodeCheck::Full
+            } else if options.iter().any(|arg| arg == "--decode") {
+                DecodeCheck::Quick
+            } else {
+                DecodeCheck::None
+            };
+            run_verify_mode(action, decode)?;
         },
         "help" | "--help" | "-h" => print_usage(),
         _ => {
@@ -44,11 +90,20 @@ fn print_usage() {
     println!("MODES:");
     println!("    rtsp                    Run RTSP stream capture mode");
     println!("    process <config_file>   Run video processing mode");
+    println!("                            (--metrics <address> serves Prometheus metrics while running)");
+    println!("    probe <url|file>        Report codec, resolution, fps, bitrate and audio of a source");
+    println!("                            (add --json for machine-readable output)");
+    println!("    catalog rebuild         Rebuild every camera's segment catalog from disk");
+    println!("    verify                  Check recorded segments for truncation or missing index");
+    println!("                            (--repair or --quarantine to fix, --decode or --full to decode)");
     println!("    help                    Show this help message");
     println!();
     println!("EXAMPLES:");
     println!("    cargo run rtsp                           # Capture RTSP streams using config.json");
     println!("    cargo run process video_config.json     # Process videos using video config");
+    println!("    cargo run probe rtsp://camera/stream     # Inspect a camera before recording it");
+    println!("    cargo run catalog rebuild                # Re-index recordings using config.json");
+    println!("    cargo run verify --repair                # Remux broken segments, quarantine the rest");
     println!("    cargo run help                           # Show help");
 }
 
@@ -57,76 +112,221 @@ fn run_rtsp_mode() -> Result<(), Box<dyn Error>> {
     println!("🎥 Starting RTSP Stream Capture Mode...");
     
     // Load configuration from file
-    let config_file = File::open("config.json")?;
-    let reader = BufReader::new(config_file);
-    let config: CaptureConfig = serde_json::from_reader(reader)?;
-
-    let mut handles = vec![];
-
-    let (urls_to_process, show_preview_for_list) = match config.saving_option {
-        SavingOption::Single => (vec![config.rtsp_url.clone()], config.show_preview),
-        SavingOption::List => (config.rtsp_url_list.clone(), false),
-        SavingOption::Both => {
-            let mut urls = vec![config.rtsp_url.clone()];
-            urls.extend(config.rtsp_url_list.clone());
-            (urls, false)
+    let config_path = Path::new("config.json");
+    let config = load_config(config_path)?;
+    let mut watcher = ConfigWatcher::new(config_path);
+
+    let cameras: Vec<_> = config
+        .resolved_cameras()
+        .into_iter()
+        .filter(|camera| camera.enabled)
+        .collect();
+
+    println!("📡 Processing {} RTSP stream(s)...", cameras.len());
+
+    // SIGINT/SIGTERM stop every capture so open segments are finalized
+    let shutdown = ShutdownSignal::install()?;
+    let signal = shutdown.clone();
+
+    let control = config.control.clone();
+    let metrics = config.metrics.clone();
+    let retention = config.retention.clone();
+    let upload = config.upload.clone();
+    let output_directory = config.output_directory.clone();
+
+    // The captures log everything themselves; dropping the receiver discards the events
+    let (mut manager, _) = RecorderManager::new(config, shutdown.clone());
+    for camera in cameras {
+        if let Err(e) = manager.add_camera(camera) {
+            eprintln!("❌ {}", e);
         }
+    }
+    let manager = Arc::new(Mutex::new(manager));
+
+    // Retention runs alongside the capture threads, on the currently configured cameras
+    let retention = retention.map(|policy| {
+        println!("🧹 Retention enabled: {:?}", policy);
+        let manager = Arc::clone(&manager);
+        RetentionManager::new(&output_directory, policy, move || {
+            manager.lock().unwrap().config().resolved_cameras()
+        })
+        .spawn(shutdown.clone())
+    });
+
+    // Closed segments are shipped to object storage in the background
+    let uploader = match upload {
+        Some(upload) => {
+            println!("☁️  Uploading segments to bucket {} at {}", upload.bucket, upload.endpoint);
+            let cameras = Arc::clone(&manager);
+            let mut uploader = Uploader::new(&output_directory, upload, move || {
+                cameras.lock().unwrap().config().resolved_cameras()
+            })?;
+            // Stopped cameras have finalized their newest segment, so it is uploaded too
+            let recording = Arc::clone(&manager);
+            uploader.recording = Some(Box::new(move |camera_id| {
+                recording
+                    .lock()
+                    .unwrap()
+                    .status(camera_id)
+                    .is_some_and(|status| status.running)
+            }));
+            Some(uploader.spawn(shutdown.clone()))
+        }
+        None => None,
     };
 
-    println!("📡 Processing {} RTSP stream(s)...", urls_to_process.len());
-
-    for url in urls_to_process {
-        let output_dir = config.output_directory.clone();
-        // For 'Both' and 'List', show_preview is false for all streams.
-        // For 'Single', it depends on the config.
-        let show_preview = if config.rtsp_url == url {
-            show_preview_for_list
-        } else {
-            false
-        };
-        let segment_duration = config.saved_time_duration;
-        let use_fps = config.use_fps;
-        let fps = config.fps;
-
-        let handle = thread::spawn(move || {
-            match RTSPCapture::new(
-                url.clone(),
-                output_dir,
-                show_preview,
-                segment_duration,
-                use_fps,
-                fps,
-            ) {
-                Ok(mut capture) => {
-                    println!("📹 Processing stream: {}", url);
-                    if let Err(e) = capture.process_stream() {
-                        eprintln!("❌ Error processing stream {}: {:?}", url, e);
-                    }
-                }
-                Err(e) => {
-                    eprintln!("❌ Failed to create RTSP capture for {}: {:?}", url, e);
+    // The control API shares the manager with the reload loop below
+    let _metrics_server = match &metrics {
+        Some(metrics) => {
+            let manager = Arc::clone(&manager);
+            let server = MetricsServer::start(
+                &metrics.bind,
+                move || render_capture_metrics(&manager.lock().unwrap().statuses()),
+                shutdown.clone(),
+            )?;
+            println!("📈 Metrics available at http://{}/metrics", server.addr());
+            Some(server)
+        }
+        None => None,
+    };
+
+    let _control_server = match &control {
+        Some(control) => {
+            let server = ControlServer::start(&control.bind, Arc::clone(&manager), shutdown)?;
+            println!("🕹️  Control API listening on http://{}", server.addr());
+            Some(server)
+        }
+        None => None,
+    };
+
+    // Apply edits of config.json until shutdown, or until every camera has stopped on its own.
+    // With the control API cameras can be started again, so it keeps running.
+    while !signal.sleep(CONFIG_POLL_INTERVAL) {
+        match watcher.poll() {
+            Some(Ok(config)) => {
+                // Stopping cameras finalize without the manager locked, so the
+                // control API and metrics keep answering
+                let (diff, stopping) = manager.lock().unwrap().begin_apply_config(config);
+                for camera in stopping {
+                    camera.wait();
                 }
+                manager.lock().unwrap().finish_apply_config(&diff);
+                println!(
+                    "🔄 Reloaded {}: {} added, {} removed, {} restarted, {} unchanged",
+                    config_path.display(),
+                    diff.added.len(),
+                    diff.removed.len(),
+                    diff.changed.len(),
+                    diff.unchanged.len()
+                );
             }
-        });
-        handles.push(handle);
-    }
+            Some(Err(e)) => eprintln!("❌ Keeping the current configuration: {}", e),
+            None => {}
+        }
 
-    // Wait for all threads to complete
-    for handle in handles {
-        handle.join().unwrap();
+        if control.is_none() && manager.lock().unwrap().all_stopped() {
+            break;
+        }
     }
 
+    // Stop the background workers too when every camera stopped on its own,
+    // then wait for all threads to complete
+    let workers = retention.into_iter().chain(uploader).collect();
+    let summaries = shutdown_recorder(&manager, &signal, workers);
+
     println!("✅ RTSP stream capture completed!");
+    println!("📊 Capture Summary:");
+    for (summary, ok) in &summaries {
+        println!(
+            "   • {}: {} after {:?}, {} restart(s)",
+            summary.camera,
+            if *ok { "stopped cleanly" } else { "stopped with error" },
+            summary.uptime,
+            summary.restarts
+        );
+    }
+    Ok(())
+}
+
+/// Report the properties of a stream or file
+fn run_probe_mode(source: &str, json: bool) -> Result<(), Box<dyn Error>> {
+    let report = probe_source(source, Duration::from_secs(10));
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report);
+    }
+    Ok(())
+}
+
+/// Rebuild the segment catalog of every configured camera
+fn run_catalog_rebuild() -> Result<(), Box<dyn Error>> {
+    let config = load_config(Path::new("config.json"))?;
+
+    for camera in config.resolved_cameras() {
+        let camera_dir = std::path::Path::new(&config.output_directory).join(camera.directory_name());
+        if !camera_dir.is_dir() {
+            println!("⏭️  {}: no recordings in {}", camera.display_name(), camera_dir.display());
+            continue;
+        }
+
+        let catalog = Catalog::for_camera_dir(&camera_dir);
+        let written = catalog.rebuild(&camera.id)?;
+        println!("📚 {}: {} segment(s) in {}", camera.display_name(), written, catalog.path().display());
+    }
+    Ok(())
+}
+
+/// Verify the segments of every configured camera
+fn run_verify_mode(action: VerifyAction, decode: DecodeCheck) -> Result<(), Box<dyn Error>> {
+    let config = load_config(Path::new("config.json"))?;
+
+    for camera in config.resolved_cameras() {
+        let camera_dir = std::path::Path::new(&config.output_directory).join(camera.directory_name());
+        let segments = list_segments(&camera_dir);
+        if segments.is_empty() {
+            println!("⏭️  {}: no recordings in {}", camera.display_name(), camera_dir.display());
+            continue;
+        }
+
+        let report = verify_segments(&segments, action, decode);
+        println!(
+            "🔍 {}: {} checked, {} broken, {} repaired, {} quarantined",
+            camera.display_name(),
+            report.checked,
+            report.issues.len(),
+            report.repaired.len(),
+            report.quarantined.len()
+        );
+        for error in &report.errors {
+            eprintln!("   • {}", error);
+        }
+    }
     Ok(())
 }
 
 /// Run video processing mode (new Process module functionality)
-fn run_process_mode(config_path: &str) -> Result<(), Box<dyn Error>> {
+fn run_process_mode(config_path: &str, metrics_bind: Option<&str>) -> Result<(), Box<dyn Error>> {
     println!("🎬 Starting Video Processing Mode...");
     println!("📄 Using config file: {}", config_path);
     
     // Create a video processor
     let mut processor = create_video_processor()?;
+
+    // Per-directory statistics can be scraped while the run is in progress
+    let _metrics_server = match metrics_bind {
+        Some(bind) => {
+            let directory_stats = processor.directory_stats();
+            let server = MetricsServer::start(
+                bind,
+                move || render_processing_metrics(&directory_stats.lock().unwrap()),
+                ShutdownSignal::new(),
+            )?;
+            println!("📈 Metrics available at http://{}/metrics", server.addr());
+            Some(server)
+        }
+        None => None,
+    };
     
     // Run video extraction with the provided config
     match processor.run_video_extraction(config_path) {